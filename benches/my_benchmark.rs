@@ -1,8 +1,7 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use rand::{seq::IteratorRandom};
 use kvs::{KvStore, KvsEngine, SledKvsEngine};
-use tempfile::NamedTempFile;
-use rand::prelude::*;
+use tempfile::TempDir;
 
 
 fn write_benchmark(c: &mut Criterion) {
@@ -11,12 +10,10 @@ fn write_benchmark(c: &mut Criterion) {
     let range = (1..100000).choose_multiple(rng, 1000).to_vec();
     group.bench_function("kvs", |b| {
         b.iter_batched(|| {
-            let path = NamedTempFile::new().expect("error creating temporary");
-            let path = path.path();
-            let kvs = KvStore::open(path);
-            kvs
+            let temp_dir = TempDir::new().expect("error creating temporary");
+            (KvStore::open(temp_dir.path()).expect("msg"), temp_dir)
         }, 
-        |mut store| {
+        |(mut store, _temp_dir)| {
             for i in &range {
                 store.set(i.to_string(), i.to_string()).expect("msg");
             }
@@ -25,8 +22,8 @@ fn write_benchmark(c: &mut Criterion) {
     });
     group.bench_function("sled", |b| {
         b.iter_batched(|| {
-            let temp_dir = NamedTempFile::new().unwrap();
-                (SledKvsEngine::new(sled::open(&temp_dir).unwrap()), temp_dir)
+            let temp_dir = TempDir::new().unwrap();
+            (SledKvsEngine::open(temp_dir.path()).unwrap(), temp_dir)
         }, 
         |(mut db, _temp_dir)| {
             for i in &range {
@@ -47,17 +44,16 @@ fn get_bench(c: &mut Criterion) {
 
     group.bench_function("kvs", |b| {
         b.iter_batched(|| {
-            let path = NamedTempFile::new().expect("error creating temporary");
-            let path = path.path();
-            let mut kvs = KvStore::open(path);
+            let temp_dir = TempDir::new().expect("error creating temporary");
+            let mut kvs = KvStore::open(temp_dir.path()).expect("msg");
 
             for i in &range {
                 kvs.set(i.to_string(), i.to_string()).expect("msg");
             }
 
-            kvs
+            (kvs, temp_dir)
         }, 
-        |store| {
+        |(store, _temp_dir)| {
             for i in &range {
                 store.get(i.to_string()).expect("msg");
             }
@@ -66,14 +62,14 @@ fn get_bench(c: &mut Criterion) {
     });
     group.bench_function("sled", |b| {
         b.iter_batched(|| {
-            let temp_dir = NamedTempFile::new().unwrap();
-            let mut db = SledKvsEngine::new(sled::open(&temp_dir).unwrap());
+            let temp_dir = TempDir::new().unwrap();
+            let mut db = SledKvsEngine::open(temp_dir.path()).unwrap();
             for i in &range {
                 db.set(i.to_string(), i.to_string()).expect("msg");
             }
-            db
+            (db, temp_dir)
         }, 
-        |db| {
+        |(db, _temp_dir)| {
             for i in &range {
                 db.get(i.to_string()).expect("msg");
             }
@@ -0,0 +1,78 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use kvs::{Features, KvsError, RequestMsg, RequestType};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+const CLIENTS: usize = 8;
+const REQUESTS: usize = 100;
+const POOLS: [&str; 3] = ["naive", "shared-queue", "work-stealing"];
+
+struct Server(Child);
+
+impl Server {
+    fn spawn(addr: &str, pool: &str, threads: u32) -> Server {
+        let child = Command::new(env!("CARGO_BIN_EXE_kvs-server"))
+            .args(["--engine", "memory", "--addr", addr, "--pool", pool, "--threads"])
+            .arg(threads.to_string())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("could not start kvs-server");
+        let server = Server(child);
+        while TcpStream::connect(addr).is_err() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        server
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+// Each client waits for every reply before sending its next request, so
+// only serving clients concurrently raises the throughput
+fn client(addr: &str, id: usize) {
+    let mut conn = TcpStream::connect(addr).unwrap();
+    conn.set_nodelay(true).unwrap();
+    kvs::handshake(&mut conn, Features::empty()).unwrap();
+    for i in 0..REQUESTS {
+        let request = match i % 2 {
+            0 => RequestMsg::build(RequestType::Put, "default", format!("key{}", id), Some(i.to_string())),
+            _ => RequestMsg::build(RequestType::Get, "default", format!("key{}", id), None),
+        };
+        kvs::write_frame(&mut conn, &kvs::with_request_id(i as u32, &request), KvsError::InvalidRequest).unwrap();
+        kvs::read_frame(&mut conn, KvsError::InvalidReply).unwrap();
+    }
+}
+
+fn concurrent_clients(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_clients");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((CLIENTS * REQUESTS) as u64));
+    let mut port = 4300;
+    for pool in POOLS {
+        for threads in [1, 2, 4, 8] {
+            let addr = format!("127.0.0.1:{}", port);
+            port += 1;
+            let _server = Server::spawn(&addr, pool, threads);
+            group.bench_with_input(BenchmarkId::new(pool, threads), &addr, |b, addr| {
+                b.iter(|| {
+                    thread::scope(|scope| {
+                        for id in 0..CLIENTS {
+                            scope.spawn(move || client(addr, id));
+                        }
+                    })
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, concurrent_clients);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+#![no_main]
+
+use kvs::ReplyMsg;
+use libfuzzer_sys::fuzz_target;
+
+// Any frame a server sends must parse or be rejected, never panic, and
+// whatever parses must be built back into the same bytes.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(msg) = ReplyMsg::parse(data) {
+        assert_eq!(msg.build(), data);
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use kvs::RequestMsg;
+use libfuzzer_sys::fuzz_target;
+
+// Any frame a client sends must parse or be rejected, never panic, and
+// whatever parses must be built back into the same bytes.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(msg) = RequestMsg::parse(data) {
+        let rebuilt = RequestMsg::build(msg.request_type, &msg.namespace, msg.key, msg.value);
+        assert_eq!(rebuilt, data);
+    }
+});
@@ -0,0 +1,69 @@
+use std::{path::PathBuf, process};
+
+use clap::Parser;
+use log::LevelFilter;
+
+/// Exit code when the log is damaged.
+const EXIT_CORRUPT: i32 = 1;
+/// Exit code when the directory could not be checked at all.
+const EXIT_FAILED: i32 = 2;
+
+#[derive(Parser, Debug)]
+#[command(
+    about = "Verifies a kvs data directory without starting the server",
+    version = env!("CARGO_PKG_VERSION")
+)]
+struct CmdOptions {
+    /// Directory holding the data, defaults to the current directory
+    #[arg(short, long)]
+    pub data_dir: Option<PathBuf>,
+    /// Print the report on a single line
+    #[arg(long)]
+    pub compact: bool,
+}
+
+fn main() {
+    env_logger::builder().filter_level(LevelFilter::Warn).init();
+
+    let options = CmdOptions::parse();
+    let path = match &options.data_dir {
+        Some(path) => path.clone(),
+        None => match std::env::current_dir() {
+            Ok(path) => path,
+            Err(err) => {
+                log::error!("Could not get current dir: {}", err);
+                process::exit(EXIT_FAILED);
+            }
+        },
+    };
+
+    match kvs::detect_engine(&path) {
+        Ok(Some(engine)) if engine != "kvs" => {
+            log::error!("{:?} was created by the {} engine, only kvs can be checked", path, engine);
+            process::exit(EXIT_FAILED);
+        }
+        Ok(_) => {}
+        Err(err) => {
+            log::error!("Could not tell which engine created {:?}: {}", path, err);
+            process::exit(EXIT_FAILED);
+        }
+    }
+
+    let report = match kvs::check_dir(&path) {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!("Could not check {:?}: {}", path, err);
+            process::exit(EXIT_FAILED);
+        }
+    };
+    let json = if options.compact {
+        serde_json::to_string(&report)
+    } else {
+        serde_json::to_string_pretty(&report)
+    };
+    println!("{}", json.expect("reports are always serializable"));
+
+    if !report.ok {
+        process::exit(EXIT_CORRUPT);
+    }
+}
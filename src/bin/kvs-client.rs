@@ -1,11 +1,45 @@
-use kvs::{RequestMsg, ReplyMsg, ReplyType};
+use kvs::{ClientOptions, ErrorCode, ExportFormat, KvsClient, KvsError, ExportWriter, ImportReader, ScanCursor, DEFAULT_NAMESPACE};
 use log::LevelFilter;
 use std::{
-    io::{Read, Write},
-    net::TcpStream,
+    collections::HashSet,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    process,
 };
 
-use clap::{arg, Command};
+use clap::{arg, ArgAction, ArgMatches, Command};
+
+/// Added to the `ErrorCode` of an error reply to form the exit code, keeping
+/// clear of the codes clap uses for usage errors.
+const EXIT_CODE_BASE: i32 = 10;
+
+/// Pairs asked for in each scan request of an export.
+const SCAN_PAGE_LEN: u32 = 1000;
+
+const EXIT_CODES: &str = "Exit codes:
+  0   success
+  1   local failure, such as an unreadable input file
+  2   invalid command line
+  11  key not found
+  12  namespace not found
+  13  namespace already exists
+  14  invalid request
+  15  not supported by the server or its engine
+  16  incompatible protocol
+  17  I/O error
+  18  corrupted data
+  19  internal server error";
+
+fn ipaddr_command() -> Command {
+    Command::new("--ipaddr").arg(arg!([Ipaddr]).help("Ipaddr").required(true))
+}
+
+fn format_arg() -> clap::Arg {
+    arg!(--format <Format>)
+        .help("File format")
+        .value_parser(["jsonl", "csv"])
+        .default_value("jsonl")
+}
 
 fn main() {
     env_logger::builder().filter_level(LevelFilter::Debug).init();
@@ -14,135 +48,197 @@ fn main() {
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
+        .subcommand_required(true)
+        .after_help(EXIT_CODES)
+        .arg(
+            arg!(--ns <Namespace>)
+                .help("The namespace to operate on")
+                .default_value(DEFAULT_NAMESPACE)
+                .global(true),
+        )
         .subcommand(
             Command::new("get")
-                .about("Set the value of a string key to a string")
+                .about("Get the string value of a given string key")
                 .arg(arg!([Key]).help("A string key").required(true))
-                .subcommand(
-                    Command::new("--ipaddr").arg(arg!([Ipaddr]).help("Ipaddr").required(true)),
-                ),
+                .subcommand(ipaddr_command()),
         )
         .subcommand(
             Command::new("set")
-                .about("about")
+                .about("Set the value of a string key to a string")
                 .arg(arg!([Key]).help("A string key").required(true))
                 .arg(
                     arg!([Value])
                         .help("The string value of the key")
                         .required(true),
                 )
-                .subcommand(
-                    Command::new("--ipaddr").arg(arg!([Ipaddr]).help("Ipaddr").required(true)),
-                ),
+                .subcommand(ipaddr_command()),
         )
         .subcommand(
             Command::new("rm")
-                .about("about")
+                .about("Remove a given key")
                 .arg(arg!([Key]).help("A string key").required(true))
-                .subcommand(
-                    Command::new("--ipaddr").arg(arg!([Ipaddr]).help("Ipaddr").required(true)),
-                ),
+                .subcommand(ipaddr_command()),
+        )
+        .subcommand(
+            Command::new("create-ns")
+                .about("Create a namespace")
+                .arg(arg!([Namespace]).help("The namespace name").required(true))
+                .subcommand(ipaddr_command()),
+        )
+        .subcommand(
+            Command::new("drop-ns")
+                .about("Drop a namespace and all of its keys")
+                .arg(arg!([Namespace]).help("The namespace name").required(true))
+                .subcommand(ipaddr_command()),
+        )
+        .subcommand(
+            Command::new("list-ns")
+                .about("List all namespaces")
+                .subcommand(ipaddr_command()),
+        )
+        .subcommand(
+            Command::new("backup")
+                .about("Back the store up while the server keeps running")
+                .arg(
+                    arg!([Dir])
+                        .help("Empty directory to write the backup to, relative to the server's backup root")
+                        .required(true),
+                )
+                .arg(
+                    arg!(--since <Parent>)
+                        .help("Only back up what changed since the backup in this directory under the backup root"),
+                )
+                .subcommand(ipaddr_command()),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Write the pairs of the namespace, or of all namespaces, to a file")
+                .arg(format_arg())
+                .arg(arg!(--prefix <Prefix>).help("Only export keys starting with this").default_value(""))
+                .arg(arg!(--all).help("Export every namespace").action(ArgAction::SetTrue))
+                .arg(arg!(--base64).help("Encode values as base64 (they are still UTF-8 text)").action(ArgAction::SetTrue))
+                .arg(arg!(--output <File>).help("Defaults to stdout"))
+                .subcommand(ipaddr_command()),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Set the pairs read from an exported file, creating missing namespaces")
+                .arg(format_arg())
+                .arg(arg!(--input <File>).help("Defaults to stdin"))
+                .subcommand(ipaddr_command()),
         )
         .get_matches();
 
+    let (name, sub_matches) = match matches.subcommand() {
+        Some(subcommand) => subcommand,
+        None => unreachable!("Exhausted list of subcommands and subcommand_required prevents `None`"),
+    };
+    let ipaddr = ipaddr(sub_matches);
+    let ns = sub_matches.get_one::<String>("ns").unwrap();
+    let arg = |id: &str| sub_matches.get_one::<String>(id).unwrap().clone();
+
+    // exports and backups of a large store may keep the server busy for long
+    let options = ClientOptions { io_timeout: None, ..ClientOptions::default() };
+    let mut client = KvsClient::connect_with_options(ipaddr.as_str(), options).unwrap_or_else(|e| fail_with(&e));
+    let result = match name {
+        "export" => return export(&mut client, ns, sub_matches),
+        "import" => return import(&mut client, sub_matches),
+        "get" => client.get_in(ns, arg("Key")).map(|value| match value {
+            Some(value) => format!("msg : {}", value),
+            None => "Key not found".to_owned(),
+        }),
+        "set" => client.set_in(ns, arg("Key"), arg("Value")).map(ok),
+        "rm" => client.remove_in(ns, arg("Key")).map(ok),
+        "create-ns" => client.create_namespace(&arg("Namespace")).map(ok),
+        "drop-ns" => client.drop_namespace(&arg("Namespace")).map(ok),
+        "list-ns" => client.list_namespaces().map(|namespaces| format!("msg : {}", namespaces.join("\n"))),
+        "backup" => client
+            .backup(&arg("Dir"), sub_matches.get_one::<String>("since").map(String::as_str))
+            .map(ok),
+        _ => unreachable!("Exhausted list of subcommands and subcommand_required prevents `None`"),
+    };
+    match result {
+        Ok(out) => println!("{}", out),
+        Err(err) => fail_with(&err),
+    }
+}
 
-    let mut ipaddr = String::from("127.0.0.1:4000");
-    
-    match matches.subcommand() {
-        Some(("get", sub_matches)) => {
-            let key = sub_matches.get_one::<String>("Key").unwrap();
+fn ok(_: ()) -> String {
+    "Ok".to_owned()
+}
 
-            match sub_matches.subcommand() {
-                Some(("--ipaddr", sub_matches)) => {
-                    let cmd = sub_matches.get_one::<String>("Ipaddr").unwrap();
-                    ipaddr = cmd.to_string();
-                }
-                _ => {}
+fn export(client: &mut KvsClient, ns: &str, sub_matches: &ArgMatches) {
+    let namespaces = if sub_matches.get_flag("all") {
+        client.list_namespaces().unwrap_or_else(|e| fail_with(&e))
+    } else {
+        vec![ns.to_owned()]
+    };
+    let out: Box<dyn Write> = match sub_matches.get_one::<String>("output") {
+        Some(path) => Box::new(File::create(path).unwrap_or_else(|e| fail(e))),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut writer = ExportWriter::new(BufWriter::new(out), format(sub_matches), sub_matches.get_flag("base64"));
+
+    let prefix = sub_matches.get_one::<String>("prefix").unwrap();
+    for ns in &namespaces {
+        let mut cursor = ScanCursor { after: None, limit: SCAN_PAGE_LEN };
+        loop {
+            let page = client.scan_in(ns, prefix, &cursor).unwrap_or_else(|e| fail_with(&e));
+            for (key, value) in page.pairs {
+                writer.write(ns, &key, &value).unwrap_or_else(|e| fail(e));
+                cursor.after = Some(key);
             }
-
-            get(ipaddr, key);
-            
-        }
-
-        Some(("set", sub_matches)) => {
-            let key = sub_matches.get_one::<String>("Key").unwrap();
-            let value = sub_matches.get_one::<String>("Value").unwrap();
-
-            match sub_matches.subcommand() {
-                Some(("--ipaddr", sub_matches)) => {
-                    let cmd = sub_matches.get_one::<String>("Ipaddr").unwrap();
-                    ipaddr = cmd.to_string();
-                }
-                _ => {}
+            if !page.more {
+                break;
             }
-
-            set(ipaddr, key, value);
         }
+    }
+    writer.finish().unwrap_or_else(|e| fail(e));
+}
 
-        Some(("rm", sub_matches)) => {
-            let key = sub_matches.get_one::<String>("Key").unwrap();
-
-            match sub_matches.subcommand() {
-                Some(("--ipaddr", sub_matches)) => {
-                    let cmd = sub_matches.get_one::<String>("Ipaddr").unwrap();
-                    ipaddr = cmd.to_string();
-                }
-                _ => {}
-            }
-
-            rm(ipaddr, key);
+fn import(client: &mut KvsClient, sub_matches: &ArgMatches) {
+    let input: Box<dyn Read> = match sub_matches.get_one::<String>("input") {
+        Some(path) => Box::new(File::open(path).unwrap_or_else(|e| fail(e))),
+        None => Box::new(io::stdin().lock()),
+    };
+    let mut namespaces: HashSet<String> =
+        client.list_namespaces().unwrap_or_else(|e| fail_with(&e)).into_iter().collect();
+    for record in ImportReader::new(BufReader::new(input), format(sub_matches)) {
+        let record = record.unwrap_or_else(|e| fail(e));
+        if !namespaces.contains(&record.ns) {
+            client.create_namespace(&record.ns).unwrap_or_else(|e| fail_with(&e));
+            namespaces.insert(record.ns.clone());
         }
-        _ => unreachable!("Exhausted list of subcommands and subcommand_required prevents `None`"),
+        client
+            .pipeline_set_in(&record.ns, record.key, record.value)
+            .unwrap_or_else(|e| fail_with(&e));
     }
+    client.finish().unwrap_or_else(|e| fail_with(&e));
+    println!("Ok");
 }
 
-fn set(ipaddr: String, key : &String, value : &String) {
-    let buf = RequestMsg::build(kvs::RequestType::Put, key.clone(), Some(value.clone()));
-    let buf = send_request(ipaddr, &buf);
-
-    let msg = ReplyMsg::parse(&buf).unwrap();
-    reply(msg);
+fn format(sub_matches: &ArgMatches) -> ExportFormat {
+    match sub_matches.get_one::<String>("format").map(String::as_str) {
+        Some("csv") => ExportFormat::Csv,
+        _ => ExportFormat::JsonLines,
+    }
 }
 
-fn get(ipaddr: String, key : &String) {
-    let buf = RequestMsg::build(kvs::RequestType::Get, key.clone(), None);
-    let buf = send_request(ipaddr, &buf);
-
-    let msg = ReplyMsg::parse(&buf).unwrap();
-    reply(msg);
+fn fail(err: impl std::fmt::Display) -> ! {
+    log::error!("{}", err);
+    process::exit(1);
 }
 
-fn rm(ipaddr: String, key : &String) {
-    let buf = RequestMsg::build(kvs::RequestType::Delete, key.clone(), None);
-    let buf = send_request(ipaddr, &buf);
-
-    let msg = ReplyMsg::parse(&buf).unwrap();
-    reply(msg);
+/// Exits with the code of the error reply `err` came from, or the code the
+/// server would have replied with for it.
+fn fail_with(err: &KvsError) -> ! {
+    eprintln!("error: {}", err);
+    process::exit(EXIT_CODE_BASE + ErrorCode::from(err) as i32);
 }
 
-fn reply(msg : ReplyMsg) {
-    log::debug!("reply: {:?}", msg);
-    match msg.reply_type {
-        ReplyType::Error => println!("err"),
-        ReplyType::Ok => println!("Ok"),
-        ReplyType::Msg => println!("msg : {}", msg.value.unwrap()),
+fn ipaddr(sub_matches: &ArgMatches) -> String {
+    if let Some(("--ipaddr", sub_matches)) = sub_matches.subcommand() {
+        return sub_matches.get_one::<String>("Ipaddr").unwrap().to_string();
     }
-}
-
-fn send_request(ipaddr: String, send_buf: &[u8]) -> Vec<u8> {
-    let mut conn = TcpStream::connect(ipaddr).unwrap();
-    
-    let mut len = (send_buf.len() as u32).to_be_bytes().to_vec();
-    log::debug!("send request len: {}, vec: {:?}", send_buf.len(), len);
-
-    conn.write_all(&mut len).unwrap();
-    conn.write_all(send_buf).unwrap();
-
-    let mut buf = [0; 4];
-    conn.read_exact(&mut buf).unwrap();
-    let len = u32::from_be_bytes(buf);
-
-    let mut buf = vec![0; len as usize];
-    conn.read_exact(&mut buf).unwrap();
-    buf
+    String::from("127.0.0.1:4000")
 }
@@ -0,0 +1,142 @@
+use std::{
+    path::{Path, PathBuf},
+    process,
+};
+
+use clap::Parser;
+use kvs::{Checkpoint, KvsClient, KvsEngine, RemoteEngine, SyncPolicy, VerifyReport};
+use log::LevelFilter;
+use serde::Serialize;
+
+/// Exit code when both sides differ after copying.
+const EXIT_DIFFERENT: i32 = 1;
+/// Exit code when the migration could not run.
+const EXIT_FAILED: i32 = 2;
+
+const ENGINES: [&str; 3] = ["kvs", "sled", "lsm"];
+
+#[derive(Parser, Debug)]
+#[command(
+    about = "Copies the data of a running kvs-server into another engine, then verifies both hold the same pairs.",
+    long_about = "Copies the data of a running kvs-server into another engine, then verifies both hold the same pairs.\n\n\
+        The source is read through the server in pages while it keeps serving its clients. Writes it takes \
+        during the copy may be missed and are reported by the verification; run again with a new checkpoint \
+        to copy the changed pairs. Removals are not carried over. The target directory must not be in use.",
+    version = env!("CARGO_PKG_VERSION")
+)]
+struct CmdOptions {
+    /// Address of the kvs-server holding the data to copy
+    #[arg(long, value_name = "IP:PORT")]
+    pub from: String,
+    /// Directory to copy the data into
+    #[arg(long)]
+    pub to: PathBuf,
+    #[arg(long, value_parser = ENGINES)]
+    pub to_engine: String,
+    /// File recording the progress, defaults to <TO>.migration.json next to the target directory
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+    /// Number of pairs copied between checkpoints
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub batch: u64,
+    /// Only compare both sides
+    #[arg(long)]
+    pub verify_only: bool,
+    /// Print the report on a single line
+    #[arg(long)]
+    pub compact: bool,
+}
+
+#[derive(Serialize)]
+struct Report {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checkpoint: Option<Checkpoint>,
+    verify: VerifyReport,
+}
+
+fn main() {
+    env_logger::builder().filter_level(LevelFilter::Warn).init();
+
+    let options = CmdOptions::parse();
+    match run(&options) {
+        Ok(true) => {}
+        Ok(false) => process::exit(EXIT_DIFFERENT),
+        Err(err) => {
+            log::error!("{}", err);
+            process::exit(EXIT_FAILED);
+        }
+    }
+}
+
+/// Migrates and verifies as asked by `options`, prints the report and
+/// returns whether both sides hold the same pairs. Errors are messages for
+/// the log.
+fn run(options: &CmdOptions) -> Result<bool, String> {
+    let client = KvsClient::connect(options.from.as_str())
+        .map_err(|err| format!("Could not connect to {}: {}", options.from, err))?;
+    let from = RemoteEngine::new(client);
+    let mut to = open_target(&options.to, &options.to_engine)?;
+
+    let checkpoint = if options.verify_only {
+        None
+    } else {
+        let path = match &options.checkpoint {
+            Some(path) => path.clone(),
+            None => default_checkpoint(&options.to)?,
+        };
+        let checkpoint = kvs::migrate(&from, to.as_mut(), &path, options.batch as usize)
+            .map_err(|err| format!("Could not migrate {} to {:?}: {}", options.from, options.to, err))?;
+        Some(checkpoint)
+    };
+    let verify = kvs::verify(&from, to.as_ref())
+        .map_err(|err| format!("Could not verify {} against {:?}: {}", options.from, options.to, err))?;
+
+    let ok = verify.ok;
+    let report = Report { checkpoint, verify };
+    let json = if options.compact {
+        serde_json::to_string(&report)
+    } else {
+        serde_json::to_string_pretty(&report)
+    };
+    println!("{}", json.expect("reports are always serializable"));
+    Ok(ok)
+}
+
+/// Path of the checkpoint next to the target directory `to`, outside the
+/// store so that the engine never sees it.
+fn default_checkpoint(to: &Path) -> Result<PathBuf, String> {
+    match to.file_name() {
+        Some(name) => Ok(to.with_file_name(format!("{}.migration.json", name.to_string_lossy()))),
+        None => Err(format!("{:?} has no name to put a checkpoint next to, pass --checkpoint", to)),
+    }
+}
+
+/// Opens the `engine` in `dir`. Syncing is left to the checkpoints.
+fn open(dir: &Path, engine: &str) -> Result<Box<dyn KvsEngine>, String> {
+    let opened: kvs::Result<Box<dyn KvsEngine>> = match engine {
+        "kvs" => kvs::KvStore::open(dir).map(|e| Box::new(e) as _),
+        "sled" => kvs::SledKvsEngine::open_with_sync(dir, SyncPolicy::Never).map(|e| Box::new(e) as _),
+        "lsm" => kvs::LsmEngine::open(dir).map(|e| Box::new(e) as _),
+        _ => unreachable!("clap only accepts known engines"),
+    };
+    opened.map_err(|err| format!("Could not open {:?}: {}", dir, err))
+}
+
+/// Opens the `engine` in the target `dir`, refusing directories created by
+/// another engine, and marks the directory once the engine opened it.
+fn open_target(dir: &Path, engine: &str) -> Result<Box<dyn KvsEngine>, String> {
+    match kvs::detect_engine(dir) {
+        Ok(Some(current)) if current != engine => {
+            return Err(format!("{:?} was created by the {} engine, not {}", dir, current, engine))
+        }
+        Ok(_) => {}
+        Err(err) => return Err(format!("Could not tell which engine created {:?}: {}", dir, err)),
+    }
+    let opened = open(dir, engine)?;
+    let marked = kvs::read_engine_marker(dir).and_then(|marker| match marker {
+        Some(_) => Ok(()),
+        None => kvs::write_engine_marker(dir, engine),
+    });
+    marked.map_err(|err| format!("Could not write engine marker: {}", err))?;
+    Ok(opened)
+}
@@ -0,0 +1,60 @@
+use std::{path::PathBuf, process};
+
+use clap::Parser;
+use log::LevelFilter;
+
+#[derive(Parser, Debug)]
+#[command(
+    about = "Salvages the intact records of a damaged kvs data directory. Stop the server first.",
+    version = env!("CARGO_PKG_VERSION")
+)]
+struct CmdOptions {
+    /// Directory holding the data, defaults to the current directory
+    #[arg(short, long)]
+    pub data_dir: Option<PathBuf>,
+    /// Print the summary on a single line
+    #[arg(long)]
+    pub compact: bool,
+}
+
+fn main() {
+    env_logger::builder().filter_level(LevelFilter::Warn).init();
+
+    let options = CmdOptions::parse();
+    let path = match &options.data_dir {
+        Some(path) => path.clone(),
+        None => match std::env::current_dir() {
+            Ok(path) => path,
+            Err(err) => {
+                log::error!("Could not get current dir: {}", err);
+                process::exit(1);
+            }
+        },
+    };
+
+    match kvs::detect_engine(&path) {
+        Ok(Some(engine)) if engine != "kvs" => {
+            log::error!("{:?} was created by the {} engine, only kvs can be repaired", path, engine);
+            process::exit(1);
+        }
+        Ok(_) => {}
+        Err(err) => {
+            log::error!("Could not tell which engine created {:?}: {}", path, err);
+            process::exit(1);
+        }
+    }
+
+    let report = match kvs::repair_dir(&path) {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!("Could not repair {:?}: {}", path, err);
+            process::exit(1);
+        }
+    };
+    let json = if options.compact {
+        serde_json::to_string(&report)
+    } else {
+        serde_json::to_string_pretty(&report)
+    };
+    println!("{}", json.expect("reports are always serializable"));
+}
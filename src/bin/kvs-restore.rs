@@ -0,0 +1,57 @@
+use std::{path::PathBuf, process};
+
+use clap::Parser;
+use kvs::RestorePoint;
+use log::LevelFilter;
+
+#[derive(Parser, Debug)]
+#[command(
+    about = "Rebuilds a kvs data directory from a full backup and the incremental backups taken after it",
+    version = env!("CARGO_PKG_VERSION")
+)]
+struct CmdOptions {
+    /// Empty or missing directory to restore into
+    #[arg(short, long)]
+    pub data_dir: PathBuf,
+    /// Stop after the record with this sequence number
+    #[arg(long, conflicts_with = "to_time")]
+    pub to_seq: Option<u64>,
+    /// Stop after the last backup taken at or before this Unix time in seconds
+    #[arg(long)]
+    pub to_time: Option<u64>,
+    /// Print the report on a single line
+    #[arg(long)]
+    pub compact: bool,
+    /// The full backup followed by incremental ones, oldest first
+    #[arg(required = true)]
+    pub backups: Vec<PathBuf>,
+}
+
+fn main() {
+    env_logger::builder().filter_level(LevelFilter::Warn).init();
+
+    let options = CmdOptions::parse();
+    let point = match (options.to_seq, options.to_time) {
+        (Some(seq), _) => RestorePoint::Seq(seq),
+        (_, Some(time)) => RestorePoint::Time(time),
+        (None, None) => RestorePoint::Latest,
+    };
+
+    let report = match kvs::restore_dir(&options.data_dir, &options.backups, point) {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!("Could not restore into {:?}: {}", options.data_dir, err);
+            process::exit(1);
+        }
+    };
+    if let Err(err) = kvs::write_engine_marker(&options.data_dir, "kvs") {
+        log::error!("Could not write engine marker: {}", err);
+        process::exit(1);
+    }
+    let json = if options.compact {
+        serde_json::to_string(&report)
+    } else {
+        serde_json::to_string_pretty(&report)
+    };
+    println!("{}", json.expect("reports are always serializable"));
+}
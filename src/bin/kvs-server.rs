@@ -1,14 +1,29 @@
 use std::{
-    io::{Read, Write},
-    net::{TcpListener, TcpStream},
-    vec,
+    collections::HashMap,
+    fs,
+    io,
+    net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::Duration,
 };
 
-use bytes::Buf;
 use clap::Parser;
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
 
-use kvs::KvsEngine;
-use kvs::{RequestMsg, ReplyMsg, RequestType};
+use kvs::{KvsEngine, NaiveThreadPool, SharedQueueThreadPool, ThreadPool, WorkStealingThreadPool};
+use kvs::{
+    Features, Hello, KvsError, RequestId, RequestMsg, ReplyMsg, RequestType, ScanCursor, ScanPage, MAX_FRAME_LEN,
+    MAX_SCAN_LIMIT,
+};
 use log::LevelFilter;
 
 
@@ -17,8 +32,35 @@ use log::LevelFilter;
 struct CmdOptions {
     #[arg(short, long, default_value = "127.0.0.1:4000")]
     pub addr: String,
-    #[arg(short, long, default_value = "kvs")]
+    #[arg(short, long, default_value = "kvs", value_parser = ["kvs", "sled", "lsm", "memory"])]
     pub engine: String,
+    /// Directory holding the data, defaults to the current directory
+    #[arg(short, long)]
+    pub data_dir: Option<PathBuf>,
+    /// Directory clients write backups under; backups are refused without it
+    #[arg(long)]
+    pub backup_root: Option<PathBuf>,
+    /// Threads serving connections; defaults to the number of CPUs
+    #[arg(long, default_value_t = default_threads(), value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: u32,
+    #[arg(long, default_value = "shared-queue", value_parser = ["naive", "shared-queue", "work-stealing"])]
+    pub pool: String,
+    /// Serve connections as tasks on an async runtime with --threads workers,
+    /// so idle connections do not hold a thread
+    #[arg(long = "async", conflicts_with = "pool")]
+    pub serve_async: bool,
+    /// Seconds to wait on SIGINT or SIGTERM for the requests in progress
+    /// before syncing the engine and exiting
+    #[arg(long, default_value_t = 10)]
+    pub drain_timeout: u64,
+    /// Seconds a connection may wait for its next request before the server
+    /// closes it, so idle clients do not keep others from being served
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    pub idle_timeout: u64,
+}
+
+fn default_threads() -> u32 {
+    thread::available_parallelism().map_or(4, |threads| threads.get() as u32)
 }
 
 fn main() {
@@ -29,72 +71,640 @@ fn main() {
 
     log::info!("Starting {} engine...", options.engine);
 
-    let mut kvs;
+    let path = match &options.data_dir {
+        Some(path) => path.clone(),
+        None => match std::env::current_dir() {
+            Ok(path) => path,
+            Err(err) => {
+                log::error!("Could not get current dir: {}", err);
+                std::process::exit(1);
+            }
+        },
+    };
+    if let Err(err) = fs::create_dir_all(&path) {
+        log::error!("Could not create data dir {:?}: {}", path, err);
+        std::process::exit(1);
+    }
+
+    if let Some(root) = &options.backup_root {
+        if let Err(err) = fs::create_dir_all(root) {
+            log::error!("Could not create backup root {:?}: {}", root, err);
+            std::process::exit(1);
+        }
+    }
+
+    if options.engine != "memory" {
+        match kvs::detect_engine(&path) {
+            Ok(Some(engine)) if engine != options.engine => {
+                log::error!(
+                    "{:?} was created by the {} engine, refusing to open it with {}",
+                    path,
+                    engine,
+                    options.engine
+                );
+                std::process::exit(1);
+            }
+            Ok(_) => {}
+            Err(err) => {
+                log::error!("Could not tell which engine created {:?}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match options.engine.as_str() {
+        "kvs" => run(&options, marked(kvs::KvStore::open(&path), &path, "kvs")),
+        "sled" => run(&options, marked(kvs::SledKvsEngine::open(&path), &path, "sled")),
+        "lsm" => run(&options, marked(kvs::LsmEngine::open(&path), &path, "lsm")),
+        "memory" => run(&options, Ok(kvs::MemoryEngine::new())),
+        _ => unreachable!("clap only accepts known engines"),
+    }
+}
+
+/// Records the engine in `dir` once it opened, if no marker says so yet.
+fn marked<E: KvsEngine>(opened: kvs::Result<E>, dir: &Path, engine: &str) -> kvs::Result<E> {
+    let kvs = opened?;
+    if kvs::read_engine_marker(dir)?.is_none() {
+        kvs::write_engine_marker(dir, engine)?;
+    }
+    Ok(kvs)
+}
 
-    if options.engine == "kvs" {
-        kvs = kvs::KvStore::new(None);
+fn run<E: KvsEngine + Send + 'static>(options: &CmdOptions, kvs: kvs::Result<E>) {
+    let kvs = match kvs {
+        Ok(kvs) => Arc::new(Mutex::new(kvs)),
+        Err(err) => {
+            log::error!("Could not open engine: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let served = if options.serve_async {
+        serve_async(options, &kvs)
     } else {
-        log::error!("Unknown engine");
-        return;
+        match options.pool.as_str() {
+            "naive" => serve::<_, NaiveThreadPool>(options, &kvs),
+            "shared-queue" => serve::<_, SharedQueueThreadPool>(options, &kvs),
+            "work-stealing" => serve::<_, WorkStealingThreadPool>(options, &kvs),
+            _ => unreachable!("clap only accepts known pools"),
+        }
+    };
+    if let Err(err) = &served {
+        log::error!("Could not accept connections: {}", err);
     }
 
-    let listener = match TcpListener::bind(&options.addr) {
+    // keep the engine locked until exiting, so connections given up on
+    // cannot write after the sync
+    let mut kvs = kvs.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Err(err) = kvs.sync() {
+        log::error!("Could not sync engine: {}", err);
+        process::exit(1);
+    }
+    log::info!("Engine synced, exiting");
+    process::exit(if served.is_ok() { 0 } else { 1 });
+}
+
+/// Accepts connections on `options.addr` and serves each on a thread of a
+/// `P` pool until SIGINT or SIGTERM, or until accepting fails for good.
+/// Requests take turns on the engine.
+fn serve<E: KvsEngine + Send + 'static, P: ThreadPool>(options: &CmdOptions, kvs: &Arc<Mutex<E>>) -> io::Result<()> {
+    let pool = match P::new(options.threads) {
+        Ok(pool) => pool,
+        Err(err) => {
+            log::error!("Could not start {} threads: {}", options.threads, err);
+            std::process::exit(1);
+        }
+    };
+
+    let addr = &options.addr;
+    let listener = match TcpListener::bind(addr) {
         Ok(listener) => listener,
         Err(err) => {
-            log::error!("Could not bind to {}: {}", options.addr, err);
+            log::error!("Could not bind to {}: {}", addr, err);
             std::process::exit(1);
         }
     };
+    let shutdown = watch_signals(listener.local_addr());
+    let connections = Arc::new(Connections::default());
+    let idle_timeout = Duration::from_secs(options.idle_timeout);
 
-    log::info!("Listening for requests on {}", options.addr);
-    while let Ok((stream, addr)) = listener.accept() {
+    log::info!("Listening for requests on {}", addr);
+    let accepted = loop {
+        let accepted = listener.accept();
+        if shutdown.load(Ordering::SeqCst) {
+            break Ok(());
+        }
+        let (stream, addr) = match accepted {
+            Ok(accepted) => accepted,
+            Err(err) if is_transient_accept(&err) => {
+                log::warn!("accept failed, retrying: {}", err);
+                thread::sleep(ACCEPT_BACKOFF);
+                continue;
+            }
+            Err(err) => break Err(err),
+        };
         log::info!("accept {}", addr.ip().to_string());
-        handle_connection(stream, &mut kvs);
+        let registered = stream
+            .set_nodelay(true)
+            .and_then(|_| stream.set_read_timeout(Some(idle_timeout)))
+            .and_then(|_| connections.register(&stream));
+        let registered = match registered {
+            Ok(registered) => registered,
+            Err(err) => {
+                log::error!("connection failed: {}", err);
+                continue;
+            }
+        };
+        let kvs = Arc::clone(kvs);
+        let backup_root = options.backup_root.clone();
+        pool.spawn(move || {
+            if let Err(err) = handle_connection(stream, &kvs, backup_root.as_deref()) {
+                log::error!("connection failed: {}", err);
+            }
+            drop(registered);
+        });
+    };
+    drain(&connections, options);
+    accepted
+}
+
+/// Accepts connections on `options.addr` and serves each as a task on a
+/// tokio runtime with `options.threads` workers until SIGINT or SIGTERM, or
+/// until accepting fails for good. Engine calls run on the runtime's
+/// blocking threads and take turns on the engine.
+fn serve_async<E: KvsEngine + Send + 'static>(options: &CmdOptions, kvs: &Arc<Mutex<E>>) -> io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(options.threads as usize)
+        .enable_all()
+        .build();
+    let runtime = match runtime {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            log::error!("Could not start {} threads: {}", options.threads, err);
+            std::process::exit(1);
+        }
+    };
+    let connections = Arc::new(Connections::default());
+    let idle_timeout = Duration::from_secs(options.idle_timeout);
+
+    let accepted = runtime.block_on(async {
+        let addr = &options.addr;
+        let listener = match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                log::error!("Could not bind to {}: {}", addr, err);
+                std::process::exit(1);
+            }
+        };
+
+        let shutdown = watch_signals(listener.local_addr());
+
+        log::info!("Listening for requests on {}", addr);
+        loop {
+            let accepted = listener.accept().await;
+            if shutdown.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            let (stream, addr) = match accepted {
+                Ok(accepted) => accepted,
+                Err(err) if is_transient_accept(&err) => {
+                    log::warn!("accept failed, retrying: {}", err);
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            log::info!("accept {}", addr.ip().to_string());
+            // registered as a std stream, which can be cloned
+            let registered = stream.into_std().and_then(|stream| {
+                stream.set_nodelay(true)?;
+                let registered = connections.register(&stream)?;
+                Ok((tokio::net::TcpStream::from_std(stream)?, registered))
+            });
+            let (stream, registered) = match registered {
+                Ok(registered) => registered,
+                Err(err) => {
+                    log::error!("connection failed: {}", err);
+                    continue;
+                }
+            };
+            let kvs = Arc::clone(kvs);
+            let backup_root = options.backup_root.clone();
+            tokio::spawn(async move {
+                if let Err(err) = handle_connection_async(stream, kvs, backup_root, idle_timeout).await {
+                    log::error!("connection failed: {}", err);
+                }
+                drop(registered);
+            });
+        }
+    });
+    drain(&connections, options);
+    // engine calls of connections given up on would keep a plain drop waiting
+    runtime.shutdown_background();
+    accepted
+}
+
+/// Pause before accepting again after a transient failure, so running out
+/// of file descriptors does not spin the accept loop.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Whether `accept` failing with `err` leaves the listener usable: the
+/// connection was dropped before it was accepted, or the process ran out of
+/// file descriptors (EMFILE and ENFILE, 24 and 23 on every unix) or memory.
+fn is_transient_accept(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::OutOfMemory
+    ) || matches!(err.raw_os_error(), Some(23) | Some(24))
+}
+
+/// Starts a thread waiting for SIGINT or SIGTERM. The first one sets the
+/// returned flag and wakes the accept loop by connecting to `addr`; a
+/// second one exits right away.
+fn watch_signals(addr: io::Result<SocketAddr>) -> Arc<AtomicBool> {
+    let watched = addr.and_then(|mut addr| {
+        if addr.ip().is_unspecified() {
+            addr.set_ip(if addr.is_ipv4() { Ipv4Addr::LOCALHOST.into() } else { Ipv6Addr::LOCALHOST.into() });
+        }
+        let mut signals = Signals::new([SIGINT, SIGTERM])?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&shutdown);
+        thread::Builder::new().name("kvs-signals".to_owned()).spawn(move || {
+            for signal in signals.forever() {
+                if flag.swap(true, Ordering::SeqCst) {
+                    log::warn!("Signal {} again, exiting without draining", signal);
+                    process::exit(1);
+                }
+                log::info!("Signal {}, shutting down", signal);
+                let _ = TcpStream::connect(addr);
+            }
+        })?;
+        Ok(shutdown)
+    });
+    match watched {
+        Ok(shutdown) => shutdown,
+        Err(err) => {
+            log::error!("Could not watch for signals: {}", err);
+            process::exit(1);
+        }
     }
 }
 
+/// Stops reading requests from the open connections and waits up to
+/// `options.drain_timeout` for the requests in progress.
+fn drain(connections: &Connections, options: &CmdOptions) {
+    let timeout = Duration::from_secs(options.drain_timeout);
+    match connections.close(timeout) {
+        0 => log::info!("All connections finished"),
+        left => log::warn!("Giving up on {} connections still busy after {:?}", left, timeout),
+    }
+}
 
-fn handle_connection(mut client_conn: TcpStream, kvs: &mut impl KvsEngine) {
+/// The connections being served, so a shutdown can stop reading requests
+/// from them and wait for the requests in progress.
+#[derive(Default)]
+struct Connections {
+    state: Mutex<ConnectionsState>,
+    /// Signalled when a connection is finished.
+    finished: Condvar,
+}
 
-    let mut buf = [0; 4];
-    client_conn.read_exact(&mut buf).unwrap();
+#[derive(Default)]
+struct ConnectionsState {
+    next_id: u64,
+    /// Clones of the open connections.
+    streams: HashMap<u64, TcpStream>,
+}
+
+/// Keeps a connection in `Connections` until its handler drops it.
+struct Registered {
+    connections: Arc<Connections>,
+    id: u64,
+}
 
-    let buf_len = (&buf[0..4]).get_u32() as usize;
-    log::debug!("request len : {}", buf_len);
-    if buf_len == 0 {
-        log::error!("request len : 0");
-        return;
+impl Connections {
+    fn register(self: &Arc<Self>, stream: &TcpStream) -> io::Result<Registered> {
+        let stream = stream.try_clone()?;
+        let mut state = self.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.streams.insert(id, stream);
+        Ok(Registered {
+            connections: Arc::clone(self),
+            id,
+        })
     }
 
-    let mut buf = vec![0; buf_len];
-    client_conn.read_exact(&mut buf).unwrap();
-    
-    let msg = RequestMsg::parse(&buf).unwrap();
-    log::debug!("request: {:?}", msg);
+    /// Shuts the reading half of every connection, so each handler returns
+    /// once it answered the request it is on, and waits up to `timeout` for
+    /// them. Returns how many are still open.
+    fn close(&self, timeout: Duration) -> usize {
+        let state = self.lock();
+        for stream in state.streams.values() {
+            let _ = stream.shutdown(Shutdown::Read);
+        }
+        let (state, _) = self
+            .finished
+            .wait_timeout_while(state, timeout, |state| !state.streams.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+        state.streams.len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ConnectionsState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        self.connections.lock().streams.remove(&self.id);
+        self.connections.finished.notify_all();
+    }
+}
+
+/// Features this server implements; the handshake drops any others.
+const SUPPORTED_FEATURES: Features = Features::PIPELINING;
+
+/// Answers the handshake and then every request sent on `client_conn`, in
+/// order, until the client closes it or leaves it idle past its read
+/// timeout. Incompatible clients, malformed and oversized requests get an
+/// error reply; only I/O errors are returned.
+fn handle_connection(
+    mut client_conn: TcpStream,
+    kvs: &Mutex<impl KvsEngine>,
+    backup_root: Option<&Path>,
+) -> kvs::Result<()> {
+    let buf = match unless_io(kvs::read_frame(&mut client_conn, KvsError::InvalidRequest)) {
+        Err(err) if is_idle(&err) => {
+            log::info!("closing idle connection");
+            return Ok(());
+        }
+        result => result?,
+    };
+    let (reply, accepted) = answer_hello(buf);
+    kvs::write_frame(&mut client_conn, &reply, KvsError::InvalidReply)?;
+    if !accepted {
+        return Ok(());
+    }
+
+    loop {
+        let buf = match unless_io(kvs::read_frame(&mut client_conn, KvsError::InvalidRequest)) {
+            // closing the connection between requests is how clients leave
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) if is_idle(&err) => {
+                log::info!("closing idle connection");
+                return Ok(());
+            }
+            result => result?,
+        };
+        let buf = match buf {
+            Ok(buf) => buf,
+            // the rest of an oversized frame was not read, so the next one
+            // cannot be found
+            Err(err) => {
+                let reply = reply_frame(0, ReplyMsg::error(&err));
+                return kvs::write_frame(&mut client_conn, &reply, KvsError::InvalidReply);
+            }
+        };
+        kvs::write_frame(&mut client_conn, &answer(&buf, kvs, backup_root), KvsError::InvalidReply)?;
+    }
+}
+
+/// Separates the I/O errors of reading a frame, which end the connection,
+/// from an oversized frame, which is answered.
+fn unless_io<T>(result: kvs::Result<T>) -> io::Result<kvs::Result<T>> {
+    match result {
+        Err(KvsError::Io(err)) => Err(err),
+        result => Ok(result),
+    }
+}
+
+/// Whether reading from a connection failed because its read timeout
+/// passed, which the platform reports as either kind.
+fn is_idle(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
 
+/// Like `handle_connection`, as a task, closing the connection once it
+/// waits for a request longer than `idle_timeout`. Requests are executed on
+/// the runtime's blocking threads so a slow engine call does not stall the
+/// other connections.
+async fn handle_connection_async<E: KvsEngine + Send + 'static>(
+    mut client_conn: tokio::net::TcpStream,
+    kvs: Arc<Mutex<E>>,
+    backup_root: Option<PathBuf>,
+    idle_timeout: Duration,
+) -> kvs::Result<()> {
+    let read = kvs::read_frame_async(&mut client_conn, KvsError::InvalidRequest);
+    let buf = match tokio::time::timeout(idle_timeout, read).await {
+        Ok(result) => unless_io(result)?,
+        Err(_) => {
+            log::info!("closing idle connection");
+            return Ok(());
+        }
+    };
+    let (reply, accepted) = answer_hello(buf);
+    kvs::write_frame_async(&mut client_conn, &reply, KvsError::InvalidReply).await?;
+    if !accepted {
+        return Ok(());
+    }
+
+    loop {
+        let read = kvs::read_frame_async(&mut client_conn, KvsError::InvalidRequest);
+        let buf = match tokio::time::timeout(idle_timeout, read).await.map(unless_io) {
+            Ok(Err(err)) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Ok(result) => result?,
+            Err(_) => {
+                log::info!("closing idle connection");
+                return Ok(());
+            }
+        };
+        let buf = match buf {
+            Ok(buf) => buf,
+            Err(err) => {
+                let reply = reply_frame(0, ReplyMsg::error(&err));
+                return kvs::write_frame_async(&mut client_conn, &reply, KvsError::InvalidReply).await;
+            }
+        };
+        let kvs = Arc::clone(&kvs);
+        let backup_root = backup_root.clone();
+        let reply = tokio::task::spawn_blocking(move || answer(&buf, &kvs, backup_root.as_deref()))
+            .await
+            .map_err(io::Error::other)?;
+        kvs::write_frame_async(&mut client_conn, &reply, KvsError::InvalidReply).await?;
+    }
+}
+
+/// Answers the handshake frame `buf`, returning the reply and whether the
+/// client may go on to send requests.
+fn answer_hello(buf: kvs::Result<Vec<u8>>) -> (Vec<u8>, bool) {
+    let buf = match buf {
+        Ok(buf) => buf,
+        Err(err) => return (ReplyMsg::error(&err).build(), false),
+    };
+    match Hello::accept(&buf, SUPPORTED_FEATURES) {
+        Ok(hello) => {
+            log::debug!("handshake: {:?}", hello);
+            (hello.build(), true)
+        }
+        Err(err) => {
+            log::error!("handshake failed: {}", err);
+            (ReplyMsg::error(&err).build(), false)
+        }
+    }
+}
+
+/// Executes the request frame `buf` and returns the reply frame. Malformed
+/// requests and failures are answered with an error reply.
+fn answer(buf: &[u8], kvs: &Mutex<impl KvsEngine>, backup_root: Option<&Path>) -> Vec<u8> {
+    let (id, result) = match kvs::split_request_id(buf) {
+        Some((id, msg)) => (
+            id,
+            RequestMsg::parse(msg).and_then(|msg| {
+                log::debug!("request {}: {:?}", id, msg);
+                if let RequestType::Backup = msg.request_type {
+                    return backup(kvs, msg, backup_root);
+                }
+                // keep serving after a request panicked while holding the engine
+                let mut kvs = kvs.lock().unwrap_or_else(PoisonError::into_inner);
+                execute(&mut *kvs, msg)
+            }),
+        ),
+        None => (0, Err(KvsError::InvalidRequest)),
+    };
+    let msg_send = match result {
+        Ok(reply) => reply,
+        Err(err) => {
+            log::error!("request {} failed: {}", id, err);
+            ReplyMsg::error(&err)
+        }
+    };
+    reply_frame(id, msg_send)
+}
+
+/// Builds the frame answering request `id` with `reply`. A frame longer
+/// than `MAX_FRAME_LEN`, which the client would refuse to read, is replaced
+/// with an error reply.
+fn reply_frame(id: RequestId, reply: ReplyMsg) -> Vec<u8> {
+    let frame = kvs::with_request_id(id, &reply.build());
+    if frame.len() <= MAX_FRAME_LEN {
+        return frame;
+    }
+    let err = KvsError::Unsupported(format!(
+        "reply of {} bytes is over the frame limit of {} bytes",
+        frame.len(),
+        MAX_FRAME_LEN
+    ));
+    log::error!("request {} failed: {}", id, err);
+    kvs::with_request_id(id, &ReplyMsg::error(&err).build())
+}
+
+fn execute(kvs: &mut impl KvsEngine, msg: RequestMsg) -> kvs::Result<ReplyMsg> {
     let mut msg_send = ReplyMsg {
         reply_type : kvs::ReplyType::Ok,
         value : None,
+        code : None,
     };
 
-
     match msg.request_type {
         RequestType::Put => {
-            kvs.set(msg.key.clone(), msg.value.clone().unwrap()).unwrap();
-            log::debug!("put {} ==> value {:?}", msg.key, kvs.get(msg.key.clone()).unwrap());
+            let value = msg.value.ok_or(KvsError::InvalidRequest)?;
+            log::debug!("put {}/{} ==> value {:?}", msg.namespace, msg.key, value);
+            kvs.set_in(&msg.namespace, msg.key, value)?;
         },
         RequestType::Delete => {
-            kvs.remove(msg.key).unwrap();
+            kvs.remove_in(&msg.namespace, msg.key)?;
         },
         RequestType::Get => {
-            msg_send.value = kvs.get(msg.key.clone()).unwrap();
+            msg_send.value = kvs.get_in(&msg.namespace, msg.key.clone())?;
+            msg_send.reply_type = kvs::ReplyType::Msg;
+            log::debug!("get {}/{} ==> value {:?}", msg.namespace, msg.key, msg_send.value);
+        }
+        RequestType::CreateNamespace => {
+            kvs.create_namespace(&msg.namespace)?;
+        }
+        RequestType::DropNamespace => {
+            kvs.drop_namespace(&msg.namespace)?;
+        }
+        RequestType::ListNamespaces => {
+            msg_send.value = Some(kvs.list_namespaces()?.join("\n"));
+            msg_send.reply_type = kvs::ReplyType::Msg;
+        }
+        RequestType::Scan => {
+            let cursor = msg.value.ok_or(KvsError::InvalidRequest)?;
+            let cursor: ScanCursor = serde_json::from_str(&cursor).map_err(|_| KvsError::InvalidRequest)?;
+            if cursor.limit == 0 {
+                return Err(KvsError::InvalidRequest);
+            }
+            let pairs = match &cursor.after {
+                Some(after) => kvs.scan_after_in(&msg.namespace, &msg.key, after)?,
+                None => kvs.scan_in(&msg.namespace, &msg.key)?,
+            };
+            let limit = cursor.limit.min(MAX_SCAN_LIMIT) as usize;
+            let mut page = ScanPage::default();
+            // room for the rest of the reply, JSON included, in the frame
+            let mut room = MAX_FRAME_LEN - 1024;
+            for pair in pairs {
+                if page.pairs.len() == limit {
+                    page.more = true;
+                    break;
+                }
+                let pair = pair?;
+                // a comma and the pair as serialized; one pair too large for
+                // a frame is still sent, and refused by `reply_frame`
+                let len = 1 + serde_json::to_vec(&pair)?.len();
+                if len > room && !page.pairs.is_empty() {
+                    page.more = true;
+                    break;
+                }
+                room = room.saturating_sub(len);
+                page.pairs.push(pair);
+            }
+            log::debug!("scan {}/{} after {:?} ==> {} pairs", msg.namespace, msg.key, cursor.after, page.pairs.len());
+            msg_send.value = Some(serde_json::to_string(&page)?);
             msg_send.reply_type = kvs::ReplyType::Msg;
-            log::debug!("get {} ==> value {:?}", msg.key, msg_send.value);
         }
+        // taken by `backup`, without holding the engine for the copy
+        RequestType::Backup => unreachable!("backup requests are not executed"),
     }
+    Ok(msg_send)
+}
 
-    let buf = msg_send.build();
-    let buf_len = buf.len() as u32;
-    client_conn.write_all(&mut buf_len.to_be_bytes().to_vec()).unwrap();
-    client_conn.write_all(&buf).unwrap();
+/// Takes the backup `msg` asks for, only holding the engine while the
+/// backup starts, so that other requests are served while it copies.
+fn backup(kvs: &Mutex<impl KvsEngine>, msg: RequestMsg, backup_root: Option<&Path>) -> kvs::Result<ReplyMsg> {
+    let dir = backup_path(backup_root, &msg.key)?;
+    let parent = msg.value.map(|parent| backup_path(backup_root, &parent)).transpose()?;
+    match &parent {
+        Some(parent) => log::info!("incremental backup to {:?} since {:?}", dir, parent),
+        None => log::info!("backup to {:?}", dir),
+    }
+    let job = kvs
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .start_backup(&dir, parent.as_deref())?;
+    job()?;
+    Ok(ReplyMsg {
+        reply_type : kvs::ReplyType::Ok,
+        value : None,
+        code : None,
+    })
+}
+
+/// Resolves the backup directory `name` sent by a client under
+/// `backup_root`. Only plain relative paths are accepted, so a client
+/// cannot make the server write outside the root.
+fn backup_path(backup_root: Option<&Path>, name: &str) -> kvs::Result<PathBuf> {
+    let root = backup_root
+        .ok_or_else(|| KvsError::Unsupported("backups are disabled, start the server with --backup-root".to_owned()))?;
+    let path = Path::new(name);
+    let plain = path.components().all(|component| matches!(component, Component::Normal(_)));
+    if name.is_empty() || !plain {
+        return Err(KvsError::Backup(format!("{:?} is not a relative path inside the backup root", name)));
+    }
+    Ok(root.join(path))
 }
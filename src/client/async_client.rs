@@ -0,0 +1,60 @@
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use super::{parse_reply, reply_value};
+use crate::common::{
+    handshake_async, read_frame_async, with_request_id, write_frame_async, Features, RequestId,
+    RequestMsg, RequestType,
+};
+use crate::{KvsError, Result, DEFAULT_NAMESPACE};
+
+/// A client for a kvs server, sending one request at a time on a single
+/// connection from a tokio runtime.
+pub struct AsyncKvsClient {
+    stream: TcpStream,
+    next_id: RequestId,
+}
+
+impl AsyncKvsClient {
+    /// Connects to the server at `addr` and performs the handshake.
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<AsyncKvsClient> {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        handshake_async(&mut stream, Features::empty()).await?;
+        Ok(AsyncKvsClient { stream, next_id: 0 })
+    }
+
+    /// Returns the value of `key`, or `None` if it is not set.
+    pub async fn get(&mut self, key: String) -> Result<Option<String>> {
+        self.request(RequestType::Get, key, None).await
+    }
+
+    /// Sets `key` to `value`.
+    pub async fn set(&mut self, key: String, value: String) -> Result<()> {
+        self.request(RequestType::Put, key, Some(value)).await?;
+        Ok(())
+    }
+
+    /// Removes `key`, failing with `KvsError::KeyNotFound` if it is not set.
+    pub async fn remove(&mut self, key: String) -> Result<()> {
+        self.request(RequestType::Delete, key, None).await?;
+        Ok(())
+    }
+
+    async fn request(
+        &mut self,
+        request_type: RequestType,
+        key: String,
+        value: Option<String>,
+    ) -> Result<Option<String>> {
+        self.next_id = self.next_id.wrapping_add(1);
+        let msg = RequestMsg::build(request_type, DEFAULT_NAMESPACE, key, value);
+        write_frame_async(
+            &mut self.stream,
+            &with_request_id(self.next_id, &msg),
+            KvsError::InvalidRequest,
+        )
+        .await?;
+        let frame = read_frame_async(&mut self.stream, KvsError::InvalidReply).await?;
+        reply_value(parse_reply(&frame, self.next_id)?)
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::VecDeque,
+    io,
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use super::{parse_reply, reply_value};
+use crate::common::{
+    handshake, read_frame, with_request_id, write_frame, Features, RequestId, RequestMsg,
+    RequestType, ScanCursor, ScanPage,
+};
+use crate::{KvsError, Result, DEFAULT_NAMESPACE};
+
+/// Most requests `KvsClient::pipeline_set_in` keeps in flight when the
+/// server allows pipelining.
+const PIPELINE_WINDOW: usize = 64;
+
+/// Timeouts of a `KvsClient`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientOptions {
+    /// Longest wait for the server to accept the connection.
+    pub connect_timeout: Duration,
+    /// Longest wait for each read or write on the connection, `None` to wait
+    /// forever.
+    pub io_timeout: Option<Duration>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            connect_timeout: Duration::from_secs(5),
+            io_timeout: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// A client for a kvs server, keeping one connection open for all of its
+/// requests.
+///
+/// After an I/O error, such as a timeout, or a reply it cannot read, the
+/// client does not know where the next reply starts and fails every later
+/// request; connect again to go on.
+pub struct KvsClient {
+    stream: TcpStream,
+    /// Most requests sent by `pipeline_set_in` before waiting for a reply.
+    window: usize,
+    next_id: RequestId,
+    /// Requests sent by `pipeline_set_in` whose reply was not read yet, in
+    /// the order the server answers them.
+    in_flight: VecDeque<RequestId>,
+    broken: bool,
+}
+
+impl KvsClient {
+    /// Connects to the server at `addr` with default timeouts.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<KvsClient> {
+        KvsClient::connect_with_options(addr, ClientOptions::default())
+    }
+
+    /// Connects to the server at `addr`, trying each address it resolves to,
+    /// and performs the handshake.
+    pub fn connect_with_options(
+        addr: impl ToSocketAddrs,
+        options: ClientOptions,
+    ) -> Result<KvsClient> {
+        let mut last_err = io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to");
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, options.connect_timeout) {
+                Ok(stream) => return KvsClient::open(stream, options),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(KvsError::Io(last_err))
+    }
+
+    fn open(mut stream: TcpStream, options: ClientOptions) -> Result<KvsClient> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(options.io_timeout)?;
+        stream.set_write_timeout(options.io_timeout)?;
+        let hello = handshake(&mut stream, Features::PIPELINING)?;
+        let window = if hello.features.contains(Features::PIPELINING) {
+            PIPELINE_WINDOW
+        } else {
+            1
+        };
+        Ok(KvsClient {
+            stream,
+            window,
+            next_id: 0,
+            in_flight: VecDeque::new(),
+            broken: false,
+        })
+    }
+
+    /// Sets the timeout of each read or write on the connection, `None` to
+    /// wait forever.
+    pub fn set_io_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.stream.set_read_timeout(timeout)?;
+        self.stream.set_write_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns `false` if the connection broke on an earlier request, has
+    /// pipelined requests unanswered, or was closed by the server since.
+    /// Checks the socket without sending anything.
+    pub fn is_healthy(&self) -> bool {
+        if self.broken || !self.in_flight.is_empty() || self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let mut buf = [0; 1];
+        // with nothing unanswered, anything to read means the server closed
+        // the connection or is out of step
+        let idle = matches!(self.stream.peek(&mut buf), Err(err) if err.kind() == io::ErrorKind::WouldBlock);
+        self.stream.set_nonblocking(false).is_ok() && idle
+    }
+
+    /// Returns the value of `key`, or `None` if it is not set.
+    pub fn get(&mut self, key: String) -> Result<Option<String>> {
+        self.get_in(DEFAULT_NAMESPACE, key)
+    }
+
+    /// Sets `key` to `value`.
+    pub fn set(&mut self, key: String, value: String) -> Result<()> {
+        self.set_in(DEFAULT_NAMESPACE, key, value)
+    }
+
+    /// Removes `key`, failing with `KvsError::KeyNotFound` if it is not set.
+    pub fn remove(&mut self, key: String) -> Result<()> {
+        self.remove_in(DEFAULT_NAMESPACE, key)
+    }
+
+    pub fn get_in(&mut self, ns: &str, key: String) -> Result<Option<String>> {
+        self.request(RequestType::Get, ns, key, None)
+    }
+
+    pub fn set_in(&mut self, ns: &str, key: String, value: String) -> Result<()> {
+        self.request(RequestType::Put, ns, key, Some(value))?;
+        Ok(())
+    }
+
+    pub fn remove_in(&mut self, ns: &str, key: String) -> Result<()> {
+        self.request(RequestType::Delete, ns, key, None)?;
+        Ok(())
+    }
+
+    pub fn create_namespace(&mut self, ns: &str) -> Result<()> {
+        self.request(RequestType::CreateNamespace, ns, String::new(), None)?;
+        Ok(())
+    }
+
+    pub fn drop_namespace(&mut self, ns: &str) -> Result<()> {
+        self.request(RequestType::DropNamespace, ns, String::new(), None)?;
+        Ok(())
+    }
+
+    pub fn list_namespaces(&mut self) -> Result<Vec<String>> {
+        let value = self.request(
+            RequestType::ListNamespaces,
+            DEFAULT_NAMESPACE,
+            String::new(),
+            None,
+        )?;
+        Ok(value
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_owned)
+            .collect())
+    }
+
+    /// Returns the page of pairs in `ns` whose key starts with `prefix`
+    /// that `cursor` asks for.
+    pub fn scan_in(&mut self, ns: &str, prefix: &str, cursor: &ScanCursor) -> Result<ScanPage> {
+        let cursor = serde_json::to_string(cursor)?;
+        let value = self.request(RequestType::Scan, ns, prefix.to_owned(), Some(cursor))?;
+        Ok(serde_json::from_str(&value.ok_or(KvsError::InvalidReply)?)?)
+    }
+
+    /// Has the server back the store up to `dir` under its backup root,
+    /// only copying what changed since the backup in `since` if given.
+    pub fn backup(&mut self, dir: &str, since: Option<&str>) -> Result<()> {
+        self.request(
+            RequestType::Backup,
+            DEFAULT_NAMESPACE,
+            dir.to_owned(),
+            since.map(str::to_owned),
+        )?;
+        Ok(())
+    }
+
+    /// Sends a request setting `key` to `value` without waiting for its
+    /// reply. A failure is returned by a later call once the window of
+    /// requests in flight is full, by any other request, or by `finish`.
+    pub fn pipeline_set_in(&mut self, ns: &str, key: String, value: String) -> Result<()> {
+        while self.in_flight.len() >= self.window {
+            self.receive_pipelined()?;
+        }
+        let id = self.send(&RequestMsg::build(RequestType::Put, ns, key, Some(value)))?;
+        self.in_flight.push_back(id);
+        Ok(())
+    }
+
+    /// Waits for the replies of all pipelined requests, returning the first
+    /// failure.
+    pub fn finish(&mut self) -> Result<()> {
+        while !self.in_flight.is_empty() {
+            self.receive_pipelined()?;
+        }
+        Ok(())
+    }
+
+    fn request(
+        &mut self,
+        request_type: RequestType,
+        ns: &str,
+        key: String,
+        value: Option<String>,
+    ) -> Result<Option<String>> {
+        self.finish()?;
+        let id = self.send(&RequestMsg::build(request_type, ns, key, value))?;
+        self.receive(id)
+    }
+
+    fn receive_pipelined(&mut self) -> Result<()> {
+        let id = self
+            .in_flight
+            .pop_front()
+            .expect("only called with requests in flight");
+        self.receive(id)?;
+        Ok(())
+    }
+
+    fn send(&mut self, msg: &[u8]) -> Result<RequestId> {
+        if self.broken {
+            return Err(broken());
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let result = write_frame(
+            &mut self.stream,
+            &with_request_id(id, msg),
+            KvsError::InvalidRequest,
+        );
+        self.check(result)?;
+        Ok(id)
+    }
+
+    fn receive(&mut self, id: RequestId) -> Result<Option<String>> {
+        if self.broken {
+            return Err(broken());
+        }
+        let result = read_frame(&mut self.stream, KvsError::InvalidReply)
+            .and_then(|frame| parse_reply(&frame, id));
+        reply_value(self.check(result)?)
+    }
+
+    /// Marks the connection broken if `result` leaves it out of step with
+    /// the server.
+    fn check<T>(&mut self, result: Result<T>) -> Result<T> {
+        if let Err(KvsError::Io(_) | KvsError::InvalidReply) = result {
+            self.broken = true;
+        }
+        result
+    }
+}
+
+fn broken() -> KvsError {
+    KvsError::Io(io::Error::new(
+        io::ErrorKind::NotConnected,
+        "the connection broke on an earlier request",
+    ))
+}
@@ -0,0 +1,37 @@
+use crate::common::{split_request_id, ErrorCode, ReplyMsg, ReplyType, RequestId};
+use crate::{KvsError, Result};
+
+/// Checks that `frame` answers request `id` and returns its reply.
+fn parse_reply(frame: &[u8], id: RequestId) -> Result<ReplyMsg> {
+    match split_request_id(frame) {
+        Some((reply_id, msg)) if reply_id == id => ReplyMsg::parse(msg),
+        _ => Err(KvsError::InvalidReply),
+    }
+}
+
+/// Returns the value `reply` carries, or the error it reports. A missing
+/// key is reported as `KvsError::KeyNotFound`, anything else the server
+/// refused as `KvsError::Server`.
+fn reply_value(reply: ReplyMsg) -> Result<Option<String>> {
+    match reply {
+        ReplyMsg {
+            reply_type: ReplyType::Error,
+            code,
+            value,
+        } => match code.unwrap_or(ErrorCode::Internal) {
+            ErrorCode::KeyNotFound => Err(KvsError::KeyNotFound),
+            code => Err(KvsError::Server(code, value.unwrap_or_default())),
+        },
+        ReplyMsg { value, .. } => Ok(value),
+    }
+}
+
+pub mod async_client;
+pub mod blocking;
+pub mod pool;
+pub mod remote;
+
+pub use async_client::AsyncKvsClient;
+pub use blocking::{ClientOptions, KvsClient};
+pub use pool::{Idempotency, KvsClientPool, PoolOptions};
+pub use remote::RemoteEngine;
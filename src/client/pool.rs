@@ -0,0 +1,304 @@
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    ops::{Deref, DerefMut},
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::blocking::{ClientOptions, KvsClient};
+use crate::{KvsError, Result, DEFAULT_NAMESPACE};
+
+/// Size, retries and deadlines of a `KvsClientPool`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolOptions {
+    /// Most connections open at once; requests wait for a free one beyond
+    /// that.
+    pub size: usize,
+    /// Timeouts of each connection. Requests still end at their deadline.
+    pub client: ClientOptions,
+    /// Longest a request may take, waiting for a connection and retries
+    /// included, unless it is given its own deadline.
+    pub request_timeout: Duration,
+    /// Most times a request failing with a transient I/O error is retried.
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled before each later one.
+    pub initial_backoff: Duration,
+    /// Longest wait before a retry.
+    pub max_backoff: Duration,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        PoolOptions {
+            size: 8,
+            client: ClientOptions::default(),
+            request_timeout: Duration::from_secs(10),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Whether a request may run twice. One that failed after it was sent may
+/// have been executed by the server, so it is only retried if running it
+/// again leaves the store as running it once would.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idempotency {
+    Idempotent,
+    NotIdempotent,
+}
+
+/// Connections to a kvs server shared by many threads.
+///
+/// Connections are opened when needed, up to `PoolOptions::size`, and kept
+/// for later requests. An idle connection is checked before it is reused,
+/// and one that broke or was closed by the server is replaced.
+pub struct KvsClientPool {
+    addrs: Vec<SocketAddr>,
+    options: PoolOptions,
+    state: Mutex<PoolState>,
+    /// Signalled when a connection is returned or given up.
+    released: Condvar,
+}
+
+struct PoolState {
+    idle: Vec<KvsClient>,
+    /// Connections idle, in use or being opened.
+    open: usize,
+}
+
+impl KvsClientPool {
+    /// Creates a pool of connections to the server at `addr`. No connection
+    /// is opened until the first request.
+    pub fn new(addr: impl ToSocketAddrs, options: PoolOptions) -> Result<KvsClientPool> {
+        if options.size == 0 {
+            return Err(KvsError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a pool needs a connection",
+            )));
+        }
+        Ok(KvsClientPool {
+            addrs: addr.to_socket_addrs()?.collect(),
+            options,
+            state: Mutex::new(PoolState {
+                idle: Vec::new(),
+                open: 0,
+            }),
+            released: Condvar::new(),
+        })
+    }
+
+    /// Returns the value of `key`, or `None` if it is not set.
+    pub fn get(&self, key: String) -> Result<Option<String>> {
+        self.get_in(DEFAULT_NAMESPACE, key)
+    }
+
+    /// Sets `key` to `value`.
+    pub fn set(&self, key: String, value: String) -> Result<()> {
+        self.set_in(DEFAULT_NAMESPACE, key, value)
+    }
+
+    /// Removes `key`, failing with `KvsError::KeyNotFound` if it is not set.
+    pub fn remove(&self, key: String) -> Result<()> {
+        self.remove_in(DEFAULT_NAMESPACE, key)
+    }
+
+    pub fn get_in(&self, ns: &str, key: String) -> Result<Option<String>> {
+        self.execute(Idempotency::Idempotent, self.deadline(), |client| {
+            client.get_in(ns, key.clone())
+        })
+    }
+
+    pub fn set_in(&self, ns: &str, key: String, value: String) -> Result<()> {
+        self.execute(Idempotency::Idempotent, self.deadline(), |client| {
+            client.set_in(ns, key.clone(), value.clone())
+        })
+    }
+
+    /// Removes `key` from `ns`. Not retried once sent, as a retry of a
+    /// removal that went through fails with `KvsError::KeyNotFound`.
+    pub fn remove_in(&self, ns: &str, key: String) -> Result<()> {
+        self.execute(Idempotency::NotIdempotent, self.deadline(), |client| {
+            client.remove_in(ns, key.clone())
+        })
+    }
+
+    /// Runs `request` on a connection of the pool and returns its result,
+    /// failing with a `TimedOut` I/O error once `deadline` passes.
+    ///
+    /// A transient I/O error, such as a refused or reset connection or a
+    /// timeout, is retried on a new connection after a backoff, up to
+    /// `PoolOptions::max_retries` times. Failures before `request` runs are
+    /// retried for every request, failures after only for idempotent ones.
+    pub fn execute<T>(
+        &self,
+        idempotency: Idempotency,
+        deadline: Instant,
+        mut request: impl FnMut(&mut KvsClient) -> Result<T>,
+    ) -> Result<T> {
+        let mut backoff = self.options.initial_backoff;
+        let mut retries = 0;
+        loop {
+            let (result, sent) = match self.checkout(deadline) {
+                Ok(mut client) => (request(&mut client), true),
+                Err(err) => (Err(err), false),
+            };
+            let err = match result {
+                Err(err)
+                    if is_transient(&err) && (!sent || idempotency == Idempotency::Idempotent) =>
+                {
+                    err
+                }
+                result => return result,
+            };
+            if retries == self.options.max_retries || Instant::now() + backoff >= deadline {
+                return Err(err);
+            }
+            log::debug!("retrying in {:?} after: {}", backoff, err);
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(self.options.max_backoff);
+            retries += 1;
+        }
+    }
+
+    fn deadline(&self) -> Instant {
+        Instant::now() + self.options.request_timeout
+    }
+
+    /// Hands out a healthy idle connection, or opens one if the pool is not
+    /// full, waiting for one to be released otherwise.
+    fn checkout(&self, deadline: Instant) -> Result<PooledClient<'_>> {
+        loop {
+            let remaining = remaining(deadline)?;
+            let mut state = self.lock();
+            if let Some(client) = state.idle.pop() {
+                drop(state);
+                if !client.is_healthy() {
+                    self.give_up();
+                    continue;
+                }
+                let client = PooledClient {
+                    pool: self,
+                    client: Some(client),
+                };
+                client.set_io_timeout(self.io_timeout(remaining))?;
+                return Ok(client);
+            }
+            if state.open < self.options.size {
+                state.open += 1;
+                drop(state);
+                let options = ClientOptions {
+                    connect_timeout: self.options.client.connect_timeout.min(remaining),
+                    io_timeout: self.io_timeout(remaining),
+                };
+                return match KvsClient::connect_with_options(&self.addrs[..], options) {
+                    Ok(client) => Ok(PooledClient {
+                        pool: self,
+                        client: Some(client),
+                    }),
+                    Err(err) => {
+                        self.give_up();
+                        Err(err)
+                    }
+                };
+            }
+            drop(
+                self.released
+                    .wait_timeout(state, remaining)
+                    .unwrap_or_else(PoisonError::into_inner),
+            );
+        }
+    }
+
+    /// Returns `client` to the pool, or drops it if it cannot be reused.
+    fn checkin(&self, client: KvsClient) {
+        if !client.is_healthy() {
+            return self.give_up();
+        }
+        self.lock().idle.push(client);
+        self.released.notify_one();
+    }
+
+    /// Makes room for another connection in place of one that was dropped.
+    fn give_up(&self) {
+        self.lock().open -= 1;
+        self.released.notify_one();
+    }
+
+    fn io_timeout(&self, remaining: Duration) -> Option<Duration> {
+        Some(
+            self.options
+                .client
+                .io_timeout
+                .map_or(remaining, |timeout| timeout.min(remaining)),
+        )
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A connection handed out by the pool, returned to it when dropped.
+struct PooledClient<'a> {
+    pool: &'a KvsClientPool,
+    client: Option<KvsClient>,
+}
+
+impl Deref for PooledClient<'_> {
+    type Target = KvsClient;
+
+    fn deref(&self) -> &KvsClient {
+        self.client.as_ref().expect("only taken when dropped")
+    }
+}
+
+impl DerefMut for PooledClient<'_> {
+    fn deref_mut(&mut self) -> &mut KvsClient {
+        self.client.as_mut().expect("only taken when dropped")
+    }
+}
+
+impl Drop for PooledClient<'_> {
+    fn drop(&mut self) {
+        let client = self.client.take().expect("only taken when dropped");
+        // a request that panicked may have left its reply unread
+        if thread::panicking() {
+            return self.pool.give_up();
+        }
+        self.pool.checkin(client);
+    }
+}
+
+/// Time left until `deadline`, failing once it passed.
+fn remaining(deadline: Instant) -> Result<Duration> {
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(remaining) if !remaining.is_zero() => Ok(remaining),
+        _ => Err(KvsError::Io(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "request deadline passed",
+        ))),
+    }
+}
+
+/// Whether `err` may not happen again on another connection.
+fn is_transient(err: &KvsError) -> bool {
+    match err {
+        KvsError::Io(err) => matches!(
+            err.kind(),
+            io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::Interrupted
+        ),
+        _ => false,
+    }
+}
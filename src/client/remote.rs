@@ -0,0 +1,95 @@
+use std::{cell::RefCell, iter};
+
+use super::blocking::KvsClient;
+use crate::common::ScanCursor;
+use crate::engines::{KvsEngine, Scan};
+use crate::Result;
+
+/// Pairs asked for in each scan request.
+const SCAN_PAGE_LEN: u32 = 1000;
+
+/// A `KvsEngine` backed by a running kvs server, so that code written
+/// against engines, such as `migrate` and `verify`, works on a live store.
+///
+/// Scans are read a page at a time as they are iterated, while the server
+/// goes on serving other clients. A pair written meanwhile is only seen if
+/// it sorts after the pages already read.
+pub struct RemoteEngine {
+    client: RefCell<KvsClient>,
+}
+
+impl RemoteEngine {
+    pub fn new(client: KvsClient) -> RemoteEngine {
+        RemoteEngine {
+            client: RefCell::new(client),
+        }
+    }
+
+    /// Scans `ns` from the first key after `after` on. The first page is
+    /// read right away, so that a missing namespace fails the call.
+    fn scan_pages(&self, ns: &str, prefix: &str, after: Option<String>) -> Result<Scan<'_>> {
+        let (ns, prefix) = (ns.to_owned(), prefix.to_owned());
+        let mut cursor = ScanCursor {
+            after,
+            limit: SCAN_PAGE_LEN,
+        };
+        let page = self.client.borrow_mut().scan_in(&ns, &prefix, &cursor)?;
+        // an empty page with more to come would ask for the same page again
+        let mut more = page.more && !page.pairs.is_empty();
+        let mut pairs = page.pairs.into_iter();
+        let pages = iter::from_fn(move || loop {
+            if let Some(pair) = pairs.next() {
+                cursor.after = Some(pair.0.clone());
+                return Some(Ok(pair));
+            }
+            if !more {
+                return None;
+            }
+            match self.client.borrow_mut().scan_in(&ns, &prefix, &cursor) {
+                Ok(page) => {
+                    more = page.more && !page.pairs.is_empty();
+                    pairs = page.pairs.into_iter();
+                }
+                Err(err) => {
+                    more = false;
+                    return Some(Err(err));
+                }
+            }
+        });
+        Ok(Box::new(pages))
+    }
+}
+
+impl KvsEngine for RemoteEngine {
+    fn set_in(&mut self, ns: &str, key: String, value: String) -> Result<()> {
+        self.client.get_mut().set_in(ns, key, value)
+    }
+
+    fn get_in(&self, ns: &str, key: String) -> Result<Option<String>> {
+        self.client.borrow_mut().get_in(ns, key)
+    }
+
+    fn remove_in(&mut self, ns: &str, key: String) -> Result<()> {
+        self.client.get_mut().remove_in(ns, key)
+    }
+
+    fn scan_in<'a>(&'a self, ns: &str, prefix: &str) -> Result<Scan<'a>> {
+        self.scan_pages(ns, prefix, None)
+    }
+
+    fn scan_after_in<'a>(&'a self, ns: &str, prefix: &str, after: &str) -> Result<Scan<'a>> {
+        self.scan_pages(ns, prefix, Some(after.to_owned()))
+    }
+
+    fn create_namespace(&mut self, ns: &str) -> Result<()> {
+        self.client.get_mut().create_namespace(ns)
+    }
+
+    fn drop_namespace(&mut self, ns: &str) -> Result<()> {
+        self.client.get_mut().drop_namespace(ns)
+    }
+
+    fn list_namespaces(&self) -> Result<Vec<String>> {
+        self.client.borrow_mut().list_namespaces()
+    }
+}
@@ -1,15 +1,43 @@
 
 use crate::error::{KvsError, Result};
 use bytes::Buf;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Read, Write},
+    ops::{BitAnd, BitOr},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Protocol version spoken by this build, sent in the handshake. Both
+/// sides must speak the same one.
+pub const PROTOCOL_VERSION : u16 = 1;
+
+/// Starts every handshake frame, so a request sent without one is told
+/// apart from a handshake.
+const HELLO_MAGIC : &[u8; 3] = b"KVS";
+
+/// Largest frame either side sends or accepts, in bytes, not counting the
+/// length prefix.
+pub const MAX_FRAME_LEN : usize = 64 * 1024 * 1024;
 
 #[derive(Debug)]
 pub enum RequestType {
     Get = 0x1,
     Put = 0x2,
     Delete = 0x3,
+    CreateNamespace = 0x4,
+    DropNamespace = 0x5,
+    ListNamespaces = 0x6,
+    /// Backs the store up into the server-side directory given as the key.
+    /// With a value, only what changed since the backup in that directory.
+    Backup = 0x7,
+    /// Lists the pairs whose key starts with the given one, a page at a
+    /// time. The value is a `ScanCursor` and the reply a `ScanPage`, both as
+    /// JSON.
+    Scan = 0x8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ReplyType {
     Error = 0x1,
     Ok = 0x2,
@@ -21,6 +49,11 @@ fn parse_request_type(t : u8) -> Result<RequestType> {
         0x1 => Ok(RequestType::Get),
         0x2 => Ok(RequestType::Put),
         0x3 => Ok(RequestType::Delete),
+        0x4 => Ok(RequestType::CreateNamespace),
+        0x5 => Ok(RequestType::DropNamespace),
+        0x6 => Ok(RequestType::ListNamespaces),
+        0x7 => Ok(RequestType::Backup),
+        0x8 => Ok(RequestType::Scan),
         _ => Err(KvsError::InvalidRequest),
     }
 }
@@ -37,6 +70,7 @@ fn parse_reply_type(t : u8) -> Result<ReplyType> {
 #[derive(Debug)]
 pub struct RequestMsg {
     pub request_type: RequestType,
+    pub namespace: String,
     pub key: String,
     pub value: Option<String>,
 }
@@ -44,14 +78,98 @@ pub struct RequestMsg {
 #[derive(Debug)]
 pub struct ReplyMsg {
     pub reply_type: ReplyType,
+    /// The value read by a `Get`, the payload of any other `Msg` reply, or
+    /// the message of an `Error` reply.
     pub value: Option<String>,
+    /// Set on `Error` replies only.
+    pub code: Option<ErrorCode>,
+}
+
+/// Which page of a scan to return, sent as the value of a `Scan` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ScanCursor {
+    /// Last key of the previous page, `None` for the first page.
+    pub after : Option<String>,
+    /// Most pairs the page may hold. The server caps it at
+    /// `MAX_SCAN_LIMIT`, and ends a page early rather than exceed
+    /// `MAX_FRAME_LEN`.
+    pub limit : u32,
+}
+
+/// Most pairs the server puts in one `ScanPage`, whatever the limit asked.
+pub const MAX_SCAN_LIMIT : u32 = 10_000;
+
+/// One page of a scan, sent as the value of the reply to a `Scan` request.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanPage {
+    pub pairs : Vec<(String, String)>,
+    /// `true` if pairs after the last one of this page are left.
+    pub more : bool,
+}
+
+/// What went wrong, sent with every `Error` reply so clients can tell
+/// failures apart without matching on messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    KeyNotFound = 0x1,
+    NamespaceNotFound = 0x2,
+    /// The namespace to create already exists.
+    Conflict = 0x3,
+    /// The request is malformed or names something invalid.
+    InvalidRequest = 0x4,
+    Unsupported = 0x5,
+    Incompatible = 0x6,
+    Io = 0x7,
+    Corruption = 0x8,
+    Internal = 0x9,
+}
+
+fn parse_error_code(code : u16) -> Result<ErrorCode> {
+    match code {
+        0x1 => Ok(ErrorCode::KeyNotFound),
+        0x2 => Ok(ErrorCode::NamespaceNotFound),
+        0x3 => Ok(ErrorCode::Conflict),
+        0x4 => Ok(ErrorCode::InvalidRequest),
+        0x5 => Ok(ErrorCode::Unsupported),
+        0x6 => Ok(ErrorCode::Incompatible),
+        0x7 => Ok(ErrorCode::Io),
+        0x8 => Ok(ErrorCode::Corruption),
+        0x9 => Ok(ErrorCode::Internal),
+        _ => Err(KvsError::InvalidReply),
+    }
+}
+
+impl From<&KvsError> for ErrorCode {
+    fn from(err : &KvsError) -> ErrorCode {
+        match err {
+            KvsError::KeyNotFound => ErrorCode::KeyNotFound,
+            KvsError::NamespaceNotFound(_) => ErrorCode::NamespaceNotFound,
+            KvsError::NamespaceExists(_) => ErrorCode::Conflict,
+            KvsError::InvalidRequest
+            | KvsError::UnexpectedCommandType
+            | KvsError::InvalidNamespace(_)
+            | KvsError::InvalidRecord(_)
+            | KvsError::Backup(_) => ErrorCode::InvalidRequest,
+            KvsError::Unsupported(_) => ErrorCode::Unsupported,
+            KvsError::Incompatible(_) => ErrorCode::Incompatible,
+            KvsError::Io(_) => ErrorCode::Io,
+            KvsError::Corruption(_) => ErrorCode::Corruption,
+            KvsError::Server(code, _) => *code,
+            KvsError::Serde(_) | KvsError::Sled(_) | KvsError::Utf8(_) | KvsError::Csv(_) | KvsError::InvalidReply => {
+                ErrorCode::Internal
+            }
+        }
+    }
 }
 
 impl RequestMsg {
-    pub fn build(request_type: RequestType, key : String, value : Option<String>) -> Vec<u8> {
+    pub fn build(request_type: RequestType, namespace : &str, key : String, value : Option<String>) -> Vec<u8> {
         let mut buf = Vec::new();
         
         buf.push(request_type as u8);
+        let namespace_len = namespace.len() as u32;
+        buf.append(&mut namespace_len.to_be_bytes().to_vec());
+        buf.append(&mut namespace.as_bytes().to_vec());
         let key_len = key.len() as u32;
         buf.append(&mut key_len.to_be_bytes().to_vec());
         buf.append(&mut key.as_bytes().to_vec());
@@ -65,49 +183,56 @@ impl RequestMsg {
         buf
     }
 
+    /// Parses a request frame, rejecting truncated fields, lengths running
+    /// past the frame, invalid UTF-8 and trailing bytes.
     pub fn parse(buf: &[u8]) -> Result<RequestMsg> {
-        let request_type = parse_request_type(buf[0])?;
+        let (&request_type, mut rest) = buf.split_first().ok_or(KvsError::InvalidRequest)?;
+        let request_type = parse_request_type(request_type)?;
 
-        let mut index = 1;
-        let key_len = (&buf[index..index + 4]).get_u32() as usize;
-        index += 4;
-        let key = String::from_utf8(buf[index..index + key_len].to_vec()).map_err(|_| {
-            KvsError::InvalidRequest
-        })?;
-        index += key_len;
-
-        match request_type {
-            RequestType::Put => {
-                let value_len = (&buf[index..index + 4]).get_u32() as usize;
-                index += 4;
-                let value = String::from_utf8(buf[index..index + value_len].to_vec()).map_err(|_| {
-                    KvsError::InvalidRequest
-                })?;
-
-                Ok(RequestMsg {
-                    request_type,
-                    key,
-                    value : Some(value),
-                })
-            }
-            _ => {
-                Ok(RequestMsg {
-                    request_type,
-                    key,
-                    value : None,
-                })
+        let namespace = take_string(&mut rest).ok_or(KvsError::InvalidRequest)?;
+        let key = take_string(&mut rest).ok_or(KvsError::InvalidRequest)?;
+        let value = match request_type {
+            RequestType::Backup if rest.is_empty() => None,
+            RequestType::Put | RequestType::Backup | RequestType::Scan => {
+                Some(take_string(&mut rest).ok_or(KvsError::InvalidRequest)?)
             }
+            _ => None,
+        };
+        if !rest.is_empty() {
+            return Err(KvsError::InvalidRequest);
         }
 
+        Ok(RequestMsg {
+            request_type,
+            namespace,
+            key,
+            value,
+        })
     }
 }
 
 
 impl ReplyMsg {
+    /// Reports `err` to the client.
+    pub fn error(err : &KvsError) -> ReplyMsg {
+        ReplyMsg {
+            reply_type : ReplyType::Error,
+            value : Some(err.to_string()),
+            code : Some(ErrorCode::from(err)),
+        }
+    }
+
     pub fn build(self) ->  Vec<u8> {
         let mut buf = vec![];
         buf.push(self.reply_type as u8);
-        if let Some(value) = self.value {
+        if let ReplyType::Error = self.reply_type {
+            let code = self.code.unwrap_or(ErrorCode::Internal) as u16;
+            buf.append(&mut code.to_be_bytes().to_vec());
+            let value = self.value.unwrap_or_default();
+            let value_len = value.len() as u32;
+            buf.append(&mut value_len.to_be_bytes().to_vec());
+            buf.append(&mut value.as_bytes().to_vec());
+        } else if let Some(value) = self.value {
             let value_len = value.len() as u32;
             buf.append(&mut value_len.to_be_bytes().to_vec());
             buf.append(&mut value.as_bytes().to_vec());
@@ -115,28 +240,261 @@ impl ReplyMsg {
         buf
     }
 
+    /// Parses a reply frame. A `Msg` reply without a value, as sent for a
+    /// missing key, has nothing after the type; an `Error` reply always has
+    /// a code and a message.
     pub fn parse(buf: &[u8]) -> Result<ReplyMsg> {
-        let reply_type = parse_reply_type(buf[0])?;
-
-        match reply_type {
-            ReplyType::Msg => {
-                let mut index = 1;        
-                let value_len = (&buf[index..index + 4]).get_u32() as usize;
-                index += 4;
-                let value = String::from_utf8(buf[index..index + value_len].to_vec()).map_err(|_| {
-                    KvsError::InvalidReply
-                })?;
-                Ok(ReplyMsg {
-                    reply_type,
-                    value : Some(value),
-                })
-            },
-            _ => {
-                Ok(ReplyMsg {
-                    reply_type,
-                    value : None,
-                })
+        let (&reply_type, mut rest) = buf.split_first().ok_or(KvsError::InvalidReply)?;
+        let reply_type = parse_reply_type(reply_type)?;
+
+        let (value, code) = match reply_type {
+            ReplyType::Error => {
+                let code = take(&mut rest, 2).ok_or(KvsError::InvalidReply)?.get_u16();
+                let message = take_string(&mut rest).ok_or(KvsError::InvalidReply)?;
+                (Some(message), Some(parse_error_code(code)?))
+            }
+            ReplyType::Msg if !rest.is_empty() => {
+                (Some(take_string(&mut rest).ok_or(KvsError::InvalidReply)?), None)
             }
+            _ => (None, None),
+        };
+        if !rest.is_empty() {
+            return Err(KvsError::InvalidReply);
+        }
+
+        Ok(ReplyMsg {
+            reply_type,
+            value,
+            code,
+        })
+    }
+}
+
+/// Splits `len` bytes off the front of `buf`, if it holds that many.
+fn take<'a>(buf : &mut &'a [u8], len : usize) -> Option<&'a [u8]> {
+    if buf.len() < len {
+        return None;
+    }
+    let (field, rest) = buf.split_at(len);
+    *buf = rest;
+    Some(field)
+}
+
+/// Splits a string prefixed with its u32 length off the front of `buf`.
+fn take_string(buf : &mut &[u8]) -> Option<String> {
+    let len = take(buf, 4)?.get_u32() as usize;
+    String::from_utf8(take(buf, len)?.to_vec()).ok()
+}
+
+/// Optional protocol features, negotiated in the handshake.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Features(u32);
+
+impl Features {
+    /// Several requests may be in flight on one connection. Without it the
+    /// client waits for each reply before sending the next request.
+    pub const PIPELINING : Features = Features(0x1);
+
+    pub const fn empty() -> Features {
+        Features(0)
+    }
+
+    /// Keeps unknown bits, which the negotiation drops.
+    pub const fn from_bits(bits : u32) -> Features {
+        Features(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other : Features) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Features {
+    type Output = Features;
+
+    fn bitor(self, other : Features) -> Features {
+        Features(self.0 | other.0)
+    }
+}
+
+impl BitAnd for Features {
+    type Output = Features;
+
+    fn bitand(self, other : Features) -> Features {
+        Features(self.0 & other.0)
+    }
+}
+
+/// The first frame in each direction of a connection: the client offers
+/// its version and the features it wants, the server answers with the
+/// version and features both sides will use, or with an error reply.
+///
+/// After the handshake the connection carries any number of requests, each
+/// answered in the order they were sent. Both carry a `RequestId`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hello {
+    pub version : u16,
+    pub features : Features,
+}
+
+impl Hello {
+    /// What this build offers when connecting with `features`.
+    pub fn new(features : Features) -> Hello {
+        Hello {
+            version : PROTOCOL_VERSION,
+            features,
+        }
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut buf = HELLO_MAGIC.to_vec();
+        buf.extend_from_slice(&self.version.to_be_bytes());
+        buf.extend_from_slice(&self.features.bits().to_be_bytes());
+        buf
+    }
+
+    /// Parses a handshake frame, or returns `None` if `buf` is not one.
+    pub fn parse(buf : &[u8]) -> Option<Hello> {
+        let mut rest = buf;
+        if take(&mut rest, HELLO_MAGIC.len())? != HELLO_MAGIC {
+            return None;
+        }
+        let version = take(&mut rest, 2)?.get_u16();
+        let features = Features::from_bits(take(&mut rest, 4)?.get_u32());
+        if !rest.is_empty() {
+            return None;
+        }
+        Some(Hello { version, features })
+    }
+
+    /// Answers the client's handshake `buf` for a server supporting
+    /// `supported`.
+    pub fn accept(buf : &[u8], supported : Features) -> Result<Hello> {
+        let hello = Hello::parse(buf).ok_or_else(|| {
+            KvsError::Incompatible(format!(
+                "expected a handshake, upgrade the client to protocol version {}",
+                PROTOCOL_VERSION
+            ))
+        })?;
+        if hello.version != PROTOCOL_VERSION {
+            return Err(KvsError::Incompatible(format!(
+                "client speaks protocol version {}, the server {}",
+                hello.version, PROTOCOL_VERSION
+            )));
+        }
+        Ok(Hello {
+            version : PROTOCOL_VERSION,
+            features : hello.features & supported,
+        })
+    }
+}
+
+/// Performs the client side of the handshake on `stream`, offering
+/// `features`, and returns what the server agreed to.
+pub fn handshake<S : Read + Write>(stream : &mut S, features : Features) -> Result<Hello> {
+    write_frame(stream, &Hello::new(features).build(), KvsError::InvalidRequest)?;
+    handshake_reply(&read_frame(stream, KvsError::InvalidReply)?)
+}
+
+/// Like `handshake`, on an async `stream`.
+pub async fn handshake_async<S : AsyncRead + AsyncWrite + Unpin>(stream : &mut S, features : Features) -> Result<Hello> {
+    write_frame_async(stream, &Hello::new(features).build(), KvsError::InvalidRequest).await?;
+    handshake_reply(&read_frame_async(stream, KvsError::InvalidReply).await?)
+}
+
+/// Checks the server's answer to a handshake.
+fn handshake_reply(buf : &[u8]) -> Result<Hello> {
+    if let Some(hello) = Hello::parse(buf) {
+        if hello.version != PROTOCOL_VERSION {
+            return Err(KvsError::Incompatible(format!(
+                "server speaks protocol version {}, the client {}",
+                hello.version, PROTOCOL_VERSION
+            )));
         }
+        return Ok(hello);
+    }
+    match ReplyMsg::parse(buf)? {
+        ReplyMsg { reply_type : ReplyType::Error, value, .. } => Err(KvsError::Incompatible(
+            value.unwrap_or_else(|| "the server rejected the handshake".to_owned()),
+        )),
+        _ => Err(KvsError::InvalidReply),
+    }
+}
+
+/// Chosen by the client for each request on a connection and sent back with
+/// its reply, so pipelined replies can be matched to their requests.
+pub type RequestId = u32;
+
+/// Prefixes the request or reply `msg` with its `id`, forming the frame sent
+/// after the handshake.
+pub fn with_request_id(id : RequestId, msg : &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(4 + msg.len());
+    frame.extend_from_slice(&id.to_be_bytes());
+    frame.extend_from_slice(msg);
+    frame
+}
+
+/// Splits a frame built by `with_request_id` into the id and the message,
+/// or returns `None` if it is too short to hold an id.
+pub fn split_request_id(frame : &[u8]) -> Option<(RequestId, &[u8])> {
+    let mut rest = frame;
+    let id = take(&mut rest, 4)?.get_u32();
+    Some((id, rest))
+}
+
+/// Writes `frame` prefixed with its u32 length, failing with `oversized`
+/// for frames longer than `MAX_FRAME_LEN`.
+pub fn write_frame(stream : &mut impl Write, frame : &[u8], oversized : KvsError) -> Result<()> {
+    stream.write_all(&frame_header(frame, oversized)?)?;
+    stream.write_all(frame)?;
+    Ok(())
+}
+
+/// Reads a frame written by `write_frame`. A length over `MAX_FRAME_LEN`
+/// fails with `oversized` before anything is allocated for it, leaving the
+/// frame itself unread.
+pub fn read_frame(stream : &mut impl Read, oversized : KvsError) -> Result<Vec<u8>> {
+    let mut header = [0; 4];
+    stream.read_exact(&mut header)?;
+    let mut buf = vec![0; frame_len(header, oversized)?];
+    stream.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Like `write_frame`, on an async `stream`.
+pub async fn write_frame_async(stream : &mut (impl AsyncWrite + Unpin), frame : &[u8], oversized : KvsError) -> Result<()> {
+    stream.write_all(&frame_header(frame, oversized)?).await?;
+    stream.write_all(frame).await?;
+    Ok(())
+}
+
+/// Like `read_frame`, on an async `stream`.
+pub async fn read_frame_async(stream : &mut (impl AsyncRead + Unpin), oversized : KvsError) -> Result<Vec<u8>> {
+    let mut header = [0; 4];
+    stream.read_exact(&mut header).await?;
+    let mut buf = vec![0; frame_len(header, oversized)?];
+    stream.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+/// The length prefix of `frame`, or `oversized` if it is too long.
+fn frame_header(frame : &[u8], oversized : KvsError) -> Result<[u8; 4]> {
+    if frame.len() > MAX_FRAME_LEN {
+        return Err(oversized);
+    }
+    Ok((frame.len() as u32).to_be_bytes())
+}
+
+/// The length of the frame announced by `header`, or `oversized` if it is
+/// over `MAX_FRAME_LEN`.
+fn frame_len(header : [u8; 4], oversized : KvsError) -> Result<usize> {
+    let len = u32::from_be_bytes(header) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(oversized);
     }
-}
\ No newline at end of file
+    Ok(len)
+}
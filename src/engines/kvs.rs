@@ -1,141 +1,377 @@
-use std::{collections::{HashMap, BTreeMap}, fs::{self, File}, path::{Path, PathBuf}, ops::Range, io::{Seek, BufReader, Read, SeekFrom, Write, BufWriter}, ffi::OsStr};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use serde::{Deserialize, Serialize};
+
+use super::vfs::{FileSystem, OsFileSystem};
+use super::wal::{check_segment, LogEnd, RecordPos, SegmentCopy, SyncPolicy, Wal};
+use super::{check_namespace_name, BackupJob, KvsEngine, Scan, DEFAULT_NAMESPACE};
 use crate::error::{KvsError, Result};
 
+const COMPACTION_THRESHOLD: u64 = 1024 * 1024;
 
-enum Command {
-    Set { key : String, value : String },
-    Remove { key : String } 
+/// A record of the log.
+#[derive(Serialize, Deserialize, Debug)]
+pub(super) enum Command {
+    Set {
+        #[serde(default = "default_namespace", skip_serializing_if = "is_default_namespace")]
+        ns : String,
+        key : String,
+        value : String,
+    },
+    Remove {
+        #[serde(default = "default_namespace", skip_serializing_if = "is_default_namespace")]
+        ns : String,
+        key : String,
+    },
+    CreateNamespace { ns : String },
+    DropNamespace { ns : String },
 }
 
-struct CommandPos {
-    gen : u64,
-    pos : u64,
-    len : u64,
+fn default_namespace() -> String {
+    DEFAULT_NAMESPACE.to_owned()
 }
 
-impl From<(u64, Range<u64>)> for CommandPos {
-    fn from((gen, range): (u64, Range<u64>)) -> Self {
-        CommandPos { gen, pos: range.start, len: range.end - range.start }
-    }
+fn is_default_namespace(ns : &str) -> bool {
+    ns == DEFAULT_NAMESPACE
 }
 
-struct BufReaderWithPos<R: Read + Seek> {
-    reader : BufReader<R>,
-    pos : u64,
-}
+/// Name of the manifest `backup_to` writes last into a backup directory.
+pub const BACKUP_MANIFEST : &str = "backup.json";
 
-impl<R: Read + Seek> BufReaderWithPos<R>{
-    fn new(mut inner : R) -> Result<Self> {
-        let pos = inner.seek(SeekFrom::Current(0))?;
-        Ok(BufReaderWithPos {
-            reader : BufReader::new(inner),
-            pos
-        })
-    }
+/// Describes a backup written by `KvStore::backup_to` or
+/// `KvStore::backup_incremental_to`.
+///
+/// Sequence numbers count the records written after the full backup that
+/// starts a chain of incremental ones.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupManifest {
+    /// Seconds since the Unix epoch.
+    pub created_at : u64,
+    /// End of the log covered by the backup this one continues, `None` for
+    /// a full backup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since : Option<LogEnd>,
+    #[serde(default)]
+    pub start_seq : u64,
+    #[serde(default)]
+    pub end_seq : u64,
+    pub segments : Vec<SegmentCopy>,
 }
 
-impl<R:Read + Seek> Read for BufReaderWithPos<R> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let len = self.reader.read(buf)?;
-        self.pos += len as u64;
-        Ok(len)
+impl BackupManifest {
+    /// Reads the manifest of the backup in `dir`.
+    pub fn read(dir : &Path) -> Result<BackupManifest> {
+        read_manifest(&OsFileSystem, dir)
     }
-}
 
-impl<R:Read + Seek> Seek for BufReaderWithPos<R> {
-    fn seek(&mut self, pos : SeekFrom) -> std::io::Result<u64> {
-        self.pos = self.reader.seek(pos)?;
-        Ok(self.pos)
+    /// End of the log covered once this backup is applied.
+    pub fn end(&self) -> LogEnd {
+        match self.segments.last() {
+            Some(last) => LogEnd { gen : last.gen, pos : last.offset + last.len },
+            None => self.since.unwrap_or_default(),
+        }
     }
 }
 
-struct BufWriteWithPos<W : Write + Seek> {
-    writer : BufWriter<W>,
-    pos : u64,
+fn read_manifest(fs : &dyn FileSystem, dir : &Path) -> Result<BackupManifest> {
+    let mut data = Vec::new();
+    fs.open_read(&dir.join(BACKUP_MANIFEST))?.read_to_end(&mut data)?;
+    Ok(serde_json::from_slice(&data)?)
 }
 
-impl<W: Write + Seek> BufWriteWithPos<W> {
-    fn new(mut inner : W) -> Result<Self> {
-        let pos = inner.seek(SeekFrom::Current(0))?;
-        Ok(BufWriteWithPos {
-            writer : BufWriter::new(inner),
-            pos
-        })
-    }
+/// Writes `manifest` into `dir`, which marks the backup there complete.
+fn write_manifest(fs : &dyn FileSystem, dir : &Path, manifest : &BackupManifest) -> Result<()> {
+    let mut file = fs.open_write(&dir.join(BACKUP_MANIFEST))?;
+    file.write_all(&serde_json::to_vec_pretty(manifest)?)?;
+    file.sync_data()?;
+    fs.sync_dir(dir)?;
+    Ok(())
 }
 
-impl<W:Write + Seek> Write for BufWriteWithPos<W> {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let len = self.writer.write(buf)?;
-        self.pos += len as u64;
-        Ok(len)
-    }
+/// Counts a backup as running for as long as it lives.
+struct RunningBackup(Arc<AtomicUsize>);
 
-    fn flush(&mut self) -> std::io::Result<()> {
-        self.writer.flush()
+impl RunningBackup {
+    fn new(backups : &Arc<AtomicUsize>) -> RunningBackup {
+        backups.fetch_add(1, Ordering::SeqCst);
+        RunningBackup(Arc::clone(backups))
     }
 }
 
-impl<W:Write + Seek> Seek for BufWriteWithPos<W> {
-    fn seek(&mut self, pos : SeekFrom) -> std::io::Result<u64> {
-        self.pos = self.writer.seek(pos)?;
-        Ok(self.pos)
+impl Drop for RunningBackup {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
     }
 }
 
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Positions of the live `Set` commands of one namespace.
+type Index = BTreeMap<String, RecordPos>;
+
+/// A log-structured key/value store in the style of Bitcask.
+///
+/// Every command is appended to a `Wal`; an in-memory index per namespace
+/// maps each key to the position of its latest `Set`. All namespaces share
+/// the log. Once enough stale records pile up the live ones are copied into
+/// a fresh generation and old ones deleted.
 pub struct KvStore {
-    path : PathBuf,
-    readers : HashMap<u64, BufReaderWithPos<File>>,
-    writer : BufWriteWithPos<File>,
-    current_pos : u64,
-    index : BTreeMap<String, CommandPos>,
+    wal : Wal,
+    fs : Arc<dyn FileSystem>,
+    namespaces : BTreeMap<String, Index>,
     uncompacted : u64,
+    /// Backups still copying sealed generations, which must not be deleted
+    /// meanwhile.
+    backups : Arc<AtomicUsize>,
 }
 
 impl KvStore {
+    /// Opens a `KvStore` at the given path, creating the directory if needed.
     pub fn open(path : impl Into<PathBuf>) -> Result<Self> {
-        let path = path.into();
-        fs::create_dir_all(&path)?;
-        
-        let mut readers = HashMap::new();
-        let mut index = BTreeMap::new();
+        KvStore::open_with_sync(path, SyncPolicy::Never)
+    }
 
-        let gen_list = sorted_gen_list(&path)?;
-        let mut uncomacted = 0;
+    /// Opens a `KvStore` whose log is synced according to `sync`.
+    pub fn open_with_sync(path : impl Into<PathBuf>, sync : SyncPolicy) -> Result<Self> {
+        KvStore::open_with_fs(path, sync, Arc::new(OsFileSystem))
+    }
 
-        for &gen in &gen_list {
-            let mut readr = BufWriteWithPos::new(File::open(log_path()));
+    /// Opens a `KvStore` whose files live on the given filesystem.
+    pub fn open_with_fs(
+        path : impl Into<PathBuf>,
+        sync : SyncPolicy,
+        fs : Arc<dyn FileSystem>,
+    ) -> Result<Self> {
+        let wal = Wal::open_with_fs(path, sync, Arc::clone(&fs))?;
+        let mut namespaces = BTreeMap::new();
+        namespaces.insert(default_namespace(), Index::new());
+        let mut uncompacted = 0;
 
+        for record in wal.replay(0) {
+            let (pos, payload) = record?;
+            match serde_json::from_slice(&payload)? {
+                Command::Set { ns, key, .. } => {
+                    let index = namespaces.entry(ns).or_default();
+                    if let Some(stale) = index.insert(key, pos) {
+                        uncompacted += stale.len;
+                    }
+                }
+                Command::Remove { ns, key } => {
+                    // the remove command itself can be dropped by the next compaction
+                    uncompacted += pos.len;
+                    let index = namespaces.entry(ns).or_default();
+                    if let Some(stale) = index.remove(&key) {
+                        uncompacted += stale.len;
+                    }
+                }
+                Command::CreateNamespace { ns } => {
+                    // compaction writes a fresh create command, which must not
+                    // clear the index when a compaction was interrupted
+                    uncompacted += pos.len;
+                    namespaces.entry(ns).or_default();
+                }
+                Command::DropNamespace { ns } => {
+                    uncompacted += pos.len;
+                    if let Some(index) = namespaces.remove(&ns) {
+                        uncompacted += index.values().map(|pos| pos.len).sum::<u64>();
+                    }
+                }
+            }
         }
 
-        unimplemented!()
+        Ok(KvStore {
+            wal,
+            fs,
+            namespaces,
+            uncompacted,
+            backups : Arc::new(AtomicUsize::new(0)),
+        })
     }
-}
 
+    /// Copies the live records into a new generation and deletes the stale ones.
+    ///
+    /// While a backup is copying the log the stale generations are kept,
+    /// and deleted by the next compaction.
+    pub fn compact(&mut self) -> Result<()> {
+        let compaction_gen = self.wal.roll()?;
+        for (ns, index) in &mut self.namespaces {
+            if ns != DEFAULT_NAMESPACE {
+                let cmd = Command::CreateNamespace { ns : ns.clone() };
+                self.wal.append(&serde_json::to_vec(&cmd)?)?;
+            }
+            for pos in index.values_mut() {
+                let payload = self.wal.read(pos)?;
+                *pos = self.wal.append(&payload)?;
+            }
+        }
+        self.wal.roll()?;
+        if self.backups.load(Ordering::SeqCst) == 0 {
+            self.wal.truncate_before(compaction_gen)?;
+        }
+        self.uncompacted = 0;
+        Ok(())
+    }
 
-fn sorted_gen_list(path: &Path) -> Result<Vec<u64>> {
-    let mut gen_list: Vec<u64> = 
-        // 读fs::read_dir 取目录并返回目录项迭代器
-        fs::read_dir(&path)?
-        // flat_map 返回转换元素的迭代器
-        .flat_map(|res| -> Result<_> { Ok(res?.path()) })
-        // filter 筛选元素返回迭代器
-        // extension 提取文件扩展名
-        .filter(|path| path.is_file() && path.extension() == Some("log".as_ref()))
-        .flat_map(|path| {
-            path.file_name()
-                .and_then(OsStr::to_str)
-                .map(|s| s.trim_end_matches(".log"))
-                .map(str::parse::<u64>)
-        })
-        .flatten()
-        .collect();
-    gen_list.sort_unstable();
-    Ok(gen_list)
+    fn append(&mut self, cmd : &Command) -> Result<RecordPos> {
+        self.wal.append(&serde_json::to_vec(cmd)?)
+    }
+
+    /// Compacts once enough stale records piled up, unless a backup is
+    /// running.
+    fn maybe_compact(&mut self) -> Result<()> {
+        if self.uncompacted > COMPACTION_THRESHOLD && self.backups.load(Ordering::SeqCst) == 0 {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Iterates over the pairs of `ns` from `start` on, for as long as their
+    /// keys start with `prefix`.
+    fn scan_from<'a>(&'a self, ns : &str, prefix : &str, start : Bound<String>) -> Result<Scan<'a>> {
+        let prefix = prefix.to_owned();
+        let pairs = self
+            .index(ns)?
+            .range((start, Bound::Unbounded))
+            .take_while(move |(key, _)| key.starts_with(&prefix))
+            .map(move |(key, pos)| match serde_json::from_slice(&self.wal.read(pos)?)? {
+                Command::Set { value, .. } => Ok((key.clone(), value)),
+                _ => Err(KvsError::UnexpectedCommandType),
+            });
+        Ok(Box::new(pairs))
+    }
+
+    fn index(&self, ns : &str) -> Result<&Index> {
+        self.namespaces
+            .get(ns)
+            .ok_or_else(|| KvsError::NamespaceNotFound(ns.to_owned()))
+    }
 }
 
+impl KvsEngine for KvStore {
+    fn set_in(&mut self, ns : &str, key : String, value : String) -> Result<()> {
+        self.index(ns)?;
+        let pos = self.append(&Command::Set { ns: ns.to_owned(), key: key.clone(), value })?;
+        if let Some(old) = self.namespaces.get_mut(ns).and_then(|index| index.insert(key, pos)) {
+            self.uncompacted += old.len;
+        }
+        self.maybe_compact()
+    }
+
+    fn get_in(&self, ns : &str, key : String) -> Result<Option<String>> {
+        let pos = match self.index(ns)?.get(&key) {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+        match serde_json::from_slice(&self.wal.read(pos)?)? {
+            Command::Set { value, .. } => Ok(Some(value)),
+            _ => Err(KvsError::UnexpectedCommandType),
+        }
+    }
+
+    fn scan_in<'a>(&'a self, ns : &str, prefix : &str) -> Result<Scan<'a>> {
+        self.scan_from(ns, prefix, Bound::Included(prefix.to_owned()))
+    }
+
+    // Starts the range past `after` instead of skipping, which would read
+    // the value of every skipped key from the log.
+    fn scan_after_in<'a>(&'a self, ns : &str, prefix : &str, after : &str) -> Result<Scan<'a>> {
+        let start = if after >= prefix {
+            Bound::Excluded(after.to_owned())
+        } else {
+            Bound::Included(prefix.to_owned())
+        };
+        self.scan_from(ns, prefix, start)
+    }
 
-fn log_path(dir: &Path, gen: u64) -> PathBuf {
-    dir.join(format!("{}.log", gen))
-}
\ No newline at end of file
+    fn remove_in(&mut self, ns : &str, key : String) -> Result<()> {
+        if !self.index(ns)?.contains_key(&key) {
+            return Err(KvsError::KeyNotFound);
+        }
+        let pos = self.append(&Command::Remove { ns: ns.to_owned(), key: key.clone() })?;
+        if let Some(old) = self.namespaces.get_mut(ns).and_then(|index| index.remove(&key)) {
+            self.uncompacted += old.len + pos.len;
+        }
+        self.maybe_compact()
+    }
+
+    fn create_namespace(&mut self, ns : &str) -> Result<()> {
+        check_namespace_name(ns)?;
+        if self.namespaces.contains_key(ns) {
+            return Err(KvsError::NamespaceExists(ns.to_owned()));
+        }
+        let pos = self.append(&Command::CreateNamespace { ns: ns.to_owned() })?;
+        self.uncompacted += pos.len;
+        self.namespaces.insert(ns.to_owned(), Index::new());
+        Ok(())
+    }
+
+    fn drop_namespace(&mut self, ns : &str) -> Result<()> {
+        check_namespace_name(ns)?;
+        self.index(ns)?;
+        let pos = self.append(&Command::DropNamespace { ns: ns.to_owned() })?;
+        if let Some(index) = self.namespaces.remove(ns) {
+            self.uncompacted += pos.len + index.values().map(|pos| pos.len).sum::<u64>();
+        }
+        self.maybe_compact()
+    }
+
+    fn list_namespaces(&self) -> Result<Vec<String>> {
+        Ok(self.namespaces.keys().cloned().collect())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.wal.sync()
+    }
+
+    /// Only seals the active generation; the job copies the sealed ones
+    /// while compaction holds off deleting them. The manifest is written
+    /// last and marks the backup complete.
+    ///
+    /// An incremental backup only ships the records appended since `parent`
+    /// was taken. A compaction rewrites the log, after which a full backup
+    /// is needed.
+    fn start_backup(&mut self, dir : &Path, parent : Option<&Path>) -> Result<BackupJob> {
+        let parent = parent.map(|parent| read_manifest(self.fs.as_ref(), parent)).transpose()?;
+        let since = parent.as_ref().map(BackupManifest::end);
+        let sealed = self.wal.seal(since)?;
+        let running = RunningBackup::new(&self.backups);
+        let fs = Arc::clone(&self.fs);
+        let dir = dir.to_owned();
+        Ok(Box::new(move || {
+            let _running = running;
+            let segments = sealed.copy_to(&dir)?;
+            let (start_seq, end_seq) = match parent {
+                Some(parent) => {
+                    let mut records = 0;
+                    for segment in &segments {
+                        check_segment(fs.as_ref(), &dir, segment.gen, |_, _| records += 1)?;
+                    }
+                    (parent.end_seq, parent.end_seq + records)
+                }
+                None => (0, 0),
+            };
+            let manifest = BackupManifest {
+                created_at : now(),
+                since,
+                start_seq,
+                end_seq,
+                segments,
+            };
+            write_manifest(fs.as_ref(), &dir, &manifest)
+        }))
+    }
+}
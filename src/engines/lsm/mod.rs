@@ -0,0 +1,529 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    ffi::OsStr,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::wal::{SyncPolicy, Wal};
+use super::{check_namespace_name, KvsEngine, Scan, DEFAULT_NAMESPACE};
+use crate::{KvsError, Result};
+
+mod sstable;
+
+use self::sstable::{Entry, Table, TableBuilder};
+
+const MAX_LEVELS: usize = 7;
+const MANIFEST: &str = "MANIFEST";
+/// Subdirectory holding the SSTables, the only place orphans are removed.
+const TABLES_DIR: &str = "sst";
+
+/// Tuning options of `LsmEngine`.
+#[derive(Debug, Clone)]
+pub struct LsmOptions {
+    /// Flush the memtable to level 0 once it holds roughly this many bytes.
+    pub memtable_size: usize,
+    /// Target size of a data block inside an SSTable.
+    pub block_size: usize,
+    /// Target size of the SSTables written by compaction.
+    pub table_size: u64,
+    /// Compact level 0 into level 1 once it holds this many tables.
+    pub l0_compaction_trigger: usize,
+    /// Maximum total size of level 1.
+    pub l1_max_bytes: u64,
+    /// Each level below level 1 may be this many times larger than the one above.
+    pub level_multiplier: u64,
+    /// When writes to the write-ahead log are synced.
+    pub sync: SyncPolicy,
+}
+
+impl Default for LsmOptions {
+    fn default() -> Self {
+        LsmOptions {
+            memtable_size: 4 << 20,
+            block_size: 4 << 10,
+            table_size: 2 << 20,
+            l0_compaction_trigger: 4,
+            l1_max_bytes: 10 << 20,
+            level_multiplier: 10,
+            sync: SyncPolicy::Never,
+        }
+    }
+}
+
+/// Set of live tables and namespaces, persisted in the `MANIFEST` file.
+#[derive(Serialize, Deserialize, Default)]
+struct Manifest {
+    next_id: u64,
+    // first log generation whose records are not in a table yet
+    log_gen: u64,
+    levels: Vec<Vec<u64>>,
+    #[serde(default)]
+    namespaces: BTreeMap<String, u64>,
+    #[serde(default)]
+    next_ns_id: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+enum LogRecord {
+    Set { key: String, value: String },
+    Remove { key: String },
+}
+
+/// A log-structured merge-tree engine.
+///
+/// Writes go to a write-ahead log and an in-memory memtable. Full memtables
+/// are flushed into sorted tables on level 0, which are compacted into
+/// non-overlapping levels of increasing size.
+///
+/// Keys are stored prefixed with the id of their namespace, so dropping a
+/// namespace only forgets its id; compaction discards the orphaned entries.
+pub struct LsmEngine {
+    path: PathBuf,
+    options: LsmOptions,
+    memtable: BTreeMap<String, Option<String>>,
+    memtable_size: usize,
+    wal: Wal,
+    log_gen: u64,
+    // level 0 is ordered from oldest to newest, other levels by key
+    levels: Vec<Vec<Table>>,
+    next_id: u64,
+    compact_cursors: Vec<String>,
+    namespaces: BTreeMap<String, u64>,
+    next_ns_id: u64,
+}
+
+impl LsmEngine {
+    /// Opens an `LsmEngine` at the given path with default options.
+    pub fn open(path: impl Into<PathBuf>) -> Result<LsmEngine> {
+        LsmEngine::open_with_options(path, LsmOptions::default())
+    }
+
+    /// Opens an `LsmEngine` at the given path.
+    ///
+    /// The directory is created if it does not exist. Tables live in the
+    /// `sst` subdirectory, and the memtable is rebuilt from the write-ahead
+    /// log in the `wal` subdirectory.
+    pub fn open_with_options(path: impl Into<PathBuf>, options: LsmOptions) -> Result<LsmEngine> {
+        let path = path.into();
+        fs::create_dir_all(&path)?;
+
+        let mut manifest = match fs::read(path.join(MANIFEST)) {
+            Ok(buf) => serde_json::from_slice(&buf)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Manifest {
+                next_id: 1,
+                ..Manifest::default()
+            },
+            Err(e) => return Err(e.into()),
+        };
+        manifest.levels.resize_with(MAX_LEVELS, Vec::new);
+        manifest
+            .namespaces
+            .entry(DEFAULT_NAMESPACE.to_owned())
+            .or_insert(0);
+        manifest.next_ns_id = manifest.next_ns_id.max(1);
+
+        fs::create_dir_all(path.join(TABLES_DIR))?;
+        let mut levels = Vec::with_capacity(MAX_LEVELS);
+        for ids in &manifest.levels {
+            let mut tables = ids
+                .iter()
+                .map(|&id| Table::open(&table_path(&path, id), id))
+                .collect::<Result<Vec<_>>>()?;
+            if !levels.is_empty() {
+                tables.sort_by(|a, b| a.smallest.cmp(&b.smallest));
+            }
+            levels.push(tables);
+        }
+        remove_orphans(&path.join(TABLES_DIR), &manifest)?;
+
+        let wal = Wal::open(path.join("wal"), options.sync)?;
+        let mut memtable = BTreeMap::new();
+        let mut memtable_size = 0;
+        for record in wal.replay(manifest.log_gen) {
+            let (_, payload) = record?;
+            let (key, value) = match serde_json::from_slice(&payload)? {
+                LogRecord::Set { key, value } => (key, Some(value)),
+                LogRecord::Remove { key } => (key, None),
+            };
+            memtable_size += entry_size(&key, value.as_deref());
+            memtable.insert(key, value);
+        }
+
+        Ok(LsmEngine {
+            path,
+            options,
+            memtable,
+            memtable_size,
+            wal,
+            log_gen: manifest.log_gen,
+            levels,
+            next_id: manifest.next_id,
+            compact_cursors: vec![String::new(); MAX_LEVELS],
+            namespaces: manifest.namespaces,
+            next_ns_id: manifest.next_ns_id,
+        })
+    }
+
+    fn write(&mut self, key: String, value: Option<String>) -> Result<()> {
+        let record = match &value {
+            Some(value) => LogRecord::Set {
+                key: key.clone(),
+                value: value.clone(),
+            },
+            None => LogRecord::Remove { key: key.clone() },
+        };
+        self.wal.append(&serde_json::to_vec(&record)?)?;
+
+        self.memtable_size += entry_size(&key, value.as_deref());
+        self.memtable.insert(key, value);
+        if self.memtable_size >= self.options.memtable_size {
+            self.flush_memtable()?;
+            self.maybe_compact()?;
+        }
+        Ok(())
+    }
+
+    fn namespace_id(&self, ns: &str) -> Result<u64> {
+        self.namespaces
+            .get(ns)
+            .copied()
+            .ok_or_else(|| KvsError::NamespaceNotFound(ns.to_owned()))
+    }
+
+    /// Looks up `key` in the memtable and tables, newest data first.
+    fn lookup(&self, key: &str) -> Result<Option<String>> {
+        if let Some(value) = self.memtable.get(key) {
+            return Ok(value.clone());
+        }
+        for table in self.levels[0].iter().rev() {
+            if let Some(value) = table.get(key)? {
+                return Ok(value);
+            }
+        }
+        for tables in &self.levels[1..] {
+            let i = tables.partition_point(|t| t.largest.as_str() < key);
+            if let Some(table) = tables.get(i) {
+                if let Some(value) = table.get(key)? {
+                    return Ok(value);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes the memtable into a new level 0 table and drops its log segments.
+    fn flush_memtable(&mut self) -> Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut builder = TableBuilder::create(&table_path(&self.path, id), self.options.block_size)?;
+        for (key, value) in &self.memtable {
+            builder.add(key, value.as_deref())?;
+        }
+        builder.finish()?;
+        self.levels[0].push(Table::open(&table_path(&self.path, id), id)?);
+
+        self.log_gen = self.wal.roll()?;
+        self.save_manifest()?;
+
+        self.wal.truncate_before(self.log_gen)?;
+        self.memtable.clear();
+        self.memtable_size = 0;
+        Ok(())
+    }
+
+    fn maybe_compact(&mut self) -> Result<()> {
+        loop {
+            if self.levels[0].len() >= self.options.l0_compaction_trigger {
+                self.compact(0)?;
+                continue;
+            }
+            let level = (1..MAX_LEVELS - 1).find(|&level| {
+                let size: u64 = self.levels[level].iter().map(|t| t.size).sum();
+                size > self.max_level_bytes(level)
+            });
+            match level {
+                Some(level) => self.compact(level)?,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn max_level_bytes(&self, level: usize) -> u64 {
+        let mut max = self.options.l1_max_bytes;
+        for _ in 1..level {
+            max = max.saturating_mul(self.options.level_multiplier);
+        }
+        max
+    }
+
+    /// Merges tables from `level` with the overlapping tables of the next level.
+    ///
+    /// Level 0 is compacted as a whole; deeper levels one table at a time,
+    /// cycling through the key space.
+    fn compact(&mut self, level: usize) -> Result<()> {
+        let upper: Vec<&Table> = if level == 0 {
+            self.levels[0].iter().rev().collect()
+        } else {
+            let tables = &self.levels[level];
+            let cursor = &self.compact_cursors[level];
+            let table = tables
+                .iter()
+                .find(|t| t.smallest.as_str() > cursor.as_str())
+                .unwrap_or(&tables[0]);
+            vec![table]
+        };
+        let smallest = upper.iter().map(|t| t.smallest.as_str()).min().unwrap_or_default();
+        let largest = upper.iter().map(|t| t.largest.as_str()).max().unwrap_or_default();
+        let lower: Vec<&Table> = self.levels[level + 1]
+            .iter()
+            .filter(|t| t.overlaps(smallest, largest))
+            .collect();
+        let drop_tombstones = self.levels[level + 2..].iter().all(Vec::is_empty);
+        let live_ids: Vec<u64> = self.namespaces.values().copied().collect();
+        let cursor = largest.to_owned();
+
+        let inputs: Vec<u64> = upper.iter().chain(&lower).map(|t| t.id).collect();
+        let mut merged = MergeIter::new(upper.iter().chain(&lower).map(|t| t.iter()).collect())?;
+        let mut next_id = self.next_id;
+        let mut outputs = Vec::new();
+        let mut builder: Option<TableBuilder> = None;
+        while let Some((key, value)) = merged.next().transpose()? {
+            if value.is_none() && drop_tombstones {
+                continue;
+            }
+            if !namespace_of(&key).is_some_and(|id| live_ids.contains(&id)) {
+                continue;
+            }
+            let current = match &mut builder {
+                Some(builder) => builder,
+                None => {
+                    outputs.push(next_id);
+                    next_id += 1;
+                    let path = table_path(&self.path, next_id - 1);
+                    builder.insert(TableBuilder::create(&path, self.options.block_size)?)
+                }
+            };
+            current.add(&key, value.as_deref())?;
+            if current.estimated_size() >= self.options.table_size {
+                if let Some(full) = builder.take() {
+                    full.finish()?;
+                }
+            }
+        }
+        if let Some(builder) = builder {
+            builder.finish()?;
+        }
+        drop(merged);
+
+        self.next_id = next_id;
+        self.compact_cursors[level] = cursor;
+        for tables in &mut self.levels[level..=level + 1] {
+            tables.retain(|t| !inputs.contains(&t.id));
+        }
+        for id in outputs {
+            self.levels[level + 1].push(Table::open(&table_path(&self.path, id), id)?);
+        }
+        self.levels[level + 1].sort_by(|a, b| a.smallest.cmp(&b.smallest));
+        self.save_manifest()?;
+
+        for id in inputs {
+            fs::remove_file(table_path(&self.path, id))?;
+        }
+        Ok(())
+    }
+
+    fn save_manifest(&self) -> Result<()> {
+        let manifest = Manifest {
+            next_id: self.next_id,
+            log_gen: self.log_gen,
+            namespaces: self.namespaces.clone(),
+            next_ns_id: self.next_ns_id,
+            levels: self
+                .levels
+                .iter()
+                .map(|tables| tables.iter().map(|t| t.id).collect())
+                .collect(),
+        };
+        save_manifest(&self.path, &manifest)
+    }
+}
+
+impl KvsEngine for LsmEngine {
+    fn set_in(&mut self, ns: &str, key: String, value: String) -> Result<()> {
+        let key = internal_key(self.namespace_id(ns)?, &key);
+        self.write(key, Some(value))
+    }
+
+    fn get_in(&self, ns: &str, key: String) -> Result<Option<String>> {
+        self.lookup(&internal_key(self.namespace_id(ns)?, &key))
+    }
+
+    fn scan_in<'a>(&'a self, ns: &str, prefix: &str) -> Result<Scan<'a>> {
+        let ns_id = self.namespace_id(ns)?;
+        let ns_prefix_len = internal_key(ns_id, "").len();
+        let start = internal_key(ns_id, prefix);
+
+        let memtable = self
+            .memtable
+            .range(start.clone()..)
+            .map(|(key, value)| Ok((key.clone(), value.clone())));
+        let mut sources: Vec<Box<dyn Iterator<Item = Result<Entry>> + 'a>> = vec![Box::new(memtable)];
+        for table in self.levels[0].iter().rev().chain(self.levels[1..].iter().flatten()) {
+            sources.push(Box::new(table.iter_from(&start)));
+        }
+
+        let pairs = MergeIter::new(sources)?
+            .skip_while({
+                let start = start.clone();
+                move |entry| matches!(entry, Ok((key, _)) if *key < start)
+            })
+            .take_while(move |entry| !matches!(entry, Ok((key, _)) if !key.starts_with(&start)))
+            .filter_map(move |entry| match entry {
+                Ok((key, Some(value))) => Some(Ok((key[ns_prefix_len..].to_owned(), value))),
+                Ok((_, None)) => None,
+                Err(e) => Some(Err(e)),
+            });
+        Ok(Box::new(pairs))
+    }
+
+    fn remove_in(&mut self, ns: &str, key: String) -> Result<()> {
+        let key = internal_key(self.namespace_id(ns)?, &key);
+        if self.lookup(&key)?.is_none() {
+            return Err(KvsError::KeyNotFound);
+        }
+        self.write(key, None)
+    }
+
+    fn create_namespace(&mut self, ns: &str) -> Result<()> {
+        check_namespace_name(ns)?;
+        if self.namespaces.contains_key(ns) {
+            return Err(KvsError::NamespaceExists(ns.to_owned()));
+        }
+        self.namespaces.insert(ns.to_owned(), self.next_ns_id);
+        self.next_ns_id += 1;
+        self.save_manifest()
+    }
+
+    fn drop_namespace(&mut self, ns: &str) -> Result<()> {
+        check_namespace_name(ns)?;
+        if self.namespaces.remove(ns).is_none() {
+            return Err(KvsError::NamespaceNotFound(ns.to_owned()));
+        }
+        self.save_manifest()
+    }
+
+    fn list_namespaces(&self) -> Result<Vec<String>> {
+        Ok(self.namespaces.keys().cloned().collect())
+    }
+
+    // flushed tables and the manifest are synced when written
+    fn sync(&mut self) -> Result<()> {
+        self.wal.sync()
+    }
+}
+
+/// Prefixes `key` with the id of its namespace.
+fn internal_key(ns_id: u64, key: &str) -> String {
+    format!("{}:{}", ns_id, key)
+}
+
+/// Returns the namespace id of an internal key.
+fn namespace_of(key: &str) -> Option<u64> {
+    key.split_once(':')?.0.parse().ok()
+}
+
+/// Merges sorted entry iterators. For duplicate keys the entry of the
+/// earliest source wins, so sources must be ordered from newest to oldest.
+struct MergeIter<I> {
+    sources: Vec<I>,
+    heap: BinaryHeap<Reverse<(String, usize, Option<String>)>>,
+}
+
+impl<I: Iterator<Item = Result<Entry>>> MergeIter<I> {
+    fn new(sources: Vec<I>) -> Result<Self> {
+        let mut iter = MergeIter {
+            sources,
+            heap: BinaryHeap::new(),
+        };
+        for source in 0..iter.sources.len() {
+            iter.advance(source)?;
+        }
+        Ok(iter)
+    }
+
+    fn advance(&mut self, source: usize) -> Result<()> {
+        if let Some((key, value)) = self.sources[source].next().transpose()? {
+            self.heap.push(Reverse((key, source, value)));
+        }
+        Ok(())
+    }
+
+    fn next_entry(&mut self) -> Result<Option<Entry>> {
+        let Reverse((key, source, value)) = match self.heap.pop() {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        self.advance(source)?;
+        while let Some(Reverse((next, source, _))) = self.heap.peek() {
+            if *next != key {
+                break;
+            }
+            let source = *source;
+            self.heap.pop();
+            self.advance(source)?;
+        }
+        Ok(Some((key, value)))
+    }
+}
+
+impl<I: Iterator<Item = Result<Entry>>> Iterator for MergeIter<I> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+fn save_manifest(dir: &Path, manifest: &Manifest) -> Result<()> {
+    let tmp = dir.join(format!("{}.tmp", MANIFEST));
+    let mut file = File::create(&tmp)?;
+    file.write_all(&serde_json::to_vec(manifest)?)?;
+    file.sync_all()?;
+    fs::rename(tmp, dir.join(MANIFEST))?;
+    Ok(())
+}
+
+/// Removes tables left behind by an interrupted flush or compaction from
+/// the tables subdirectory `dir`.
+fn remove_orphans(dir: &Path, manifest: &Manifest) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() != Some("sst".as_ref()) {
+            continue;
+        }
+        let id = path
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .and_then(|s| s.parse::<u64>().ok());
+        if let Some(id) = id {
+            if !manifest.levels.iter().any(|ids| ids.contains(&id)) {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn entry_size(key: &str, value: Option<&str>) -> usize {
+    key.len() + value.map_or(0, str::len) + 16
+}
+
+fn table_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(TABLES_DIR).join(format!("{}.sst", id))
+}
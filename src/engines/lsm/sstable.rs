@@ -0,0 +1,343 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use bytes::{Buf, BufMut};
+
+use crate::{KvsError, Result};
+
+/// A key and either its value or a tombstone.
+pub(super) type Entry = (String, Option<String>);
+
+const MAGIC: u64 = 0x6b76_735f_6c73_6d31;
+const FOOTER_LEN: u64 = 8 + 4 + 8;
+/// Encoded length of a block handle with an empty last key.
+const HANDLE_MIN_LEN: usize = 4 + 8 + 4;
+
+const KIND_VALUE: u8 = 0;
+const KIND_TOMBSTONE: u8 = 1;
+
+/// Location of a data block and the last key stored in it.
+struct BlockHandle {
+    last_key: String,
+    offset: u64,
+    len: u32,
+}
+
+/// Writes sorted entries into a new SSTable file.
+///
+/// Layout: data blocks, an index block and a fixed size footer. Every block
+/// ends with a crc32 of its contents.
+pub(super) struct TableBuilder {
+    writer: BufWriter<File>,
+    block: Vec<u8>,
+    block_size: usize,
+    index: Vec<BlockHandle>,
+    offset: u64,
+    smallest: Option<String>,
+    last_key: String,
+}
+
+impl TableBuilder {
+    pub fn create(path: &Path, block_size: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        Ok(TableBuilder {
+            writer: BufWriter::new(file),
+            block: Vec::new(),
+            block_size,
+            index: Vec::new(),
+            offset: 0,
+            smallest: None,
+            last_key: String::new(),
+        })
+    }
+
+    /// Appends an entry. Keys must be added in strictly increasing order.
+    pub fn add(&mut self, key: &str, value: Option<&str>) -> Result<()> {
+        if self.smallest.is_none() {
+            self.smallest = Some(key.to_owned());
+        }
+        put_str(&mut self.block, key);
+        match value {
+            Some(value) => {
+                self.block.put_u8(KIND_VALUE);
+                put_str(&mut self.block, value);
+            }
+            None => self.block.put_u8(KIND_TOMBSTONE),
+        }
+        self.last_key.clear();
+        self.last_key.push_str(key);
+
+        if self.block.len() >= self.block_size {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    /// Size of the file if it were finished now.
+    pub fn estimated_size(&self) -> u64 {
+        self.offset + self.block.len() as u64
+    }
+
+    /// Writes the index and footer, syncs the file and returns the number
+    /// of bytes written.
+    pub fn finish(mut self) -> Result<u64> {
+        if !self.block.is_empty() {
+            self.flush_block()?;
+        }
+
+        let mut index = Vec::new();
+        put_str(&mut index, self.smallest.as_deref().unwrap_or_default());
+        index.put_u32(self.index.len() as u32);
+        for handle in &self.index {
+            put_str(&mut index, &handle.last_key);
+            index.put_u64(handle.offset);
+            index.put_u32(handle.len);
+        }
+        let index_offset = self.offset;
+        let index_len = write_block(&mut self.writer, &index)?;
+
+        let mut footer = Vec::with_capacity(FOOTER_LEN as usize);
+        footer.put_u64(index_offset);
+        footer.put_u32(index_len);
+        footer.put_u64(MAGIC);
+        self.writer.write_all(&footer)?;
+
+        let file = self.writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok(index_offset + index_len as u64 + FOOTER_LEN)
+    }
+
+    fn flush_block(&mut self) -> Result<()> {
+        let len = write_block(&mut self.writer, &self.block)?;
+        self.index.push(BlockHandle {
+            last_key: self.last_key.clone(),
+            offset: self.offset,
+            len,
+        });
+        self.offset += len as u64;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+/// An immutable, sorted table on disk. Only the index is kept in memory.
+pub(super) struct Table {
+    pub id: u64,
+    pub smallest: String,
+    pub largest: String,
+    pub size: u64,
+    file: File,
+    index: Vec<BlockHandle>,
+}
+
+impl Table {
+    pub fn open(path: &Path, id: u64) -> Result<Table> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        if size < FOOTER_LEN {
+            return Err(corrupted(id, "file too short"));
+        }
+
+        file.seek(SeekFrom::Start(size - FOOTER_LEN))?;
+        let mut footer = [0; FOOTER_LEN as usize];
+        file.read_exact(&mut footer)?;
+        let mut footer = &footer[..];
+        let index_offset = footer.get_u64();
+        let index_len = footer.get_u32();
+        // the footer has no checksum, so its fields may be anything
+        let end = index_offset
+            .checked_add(index_len as u64)
+            .and_then(|end| end.checked_add(FOOTER_LEN));
+        if footer.get_u64() != MAGIC || end != Some(size) {
+            return Err(corrupted(id, "bad footer"));
+        }
+
+        let block = read_block(&file, index_offset, index_len).map_err(|e| with_id(e, id))?;
+        let mut buf = &block[..];
+        let smallest = get_str(&mut buf).ok_or_else(|| corrupted(id, "bad index"))?;
+        let count = get_u32(&mut buf).ok_or_else(|| corrupted(id, "bad index"))?;
+        // the count is read from disk, so only trust it as far as the block
+        // has room for that many handles
+        let mut index = Vec::with_capacity((count as usize).min(buf.len() / HANDLE_MIN_LEN));
+        for _ in 0..count {
+            let last_key = get_str(&mut buf);
+            let offset = get_u64(&mut buf);
+            let len = get_u32(&mut buf);
+            match (last_key, offset, len) {
+                (Some(last_key), Some(offset), Some(len)) => index.push(BlockHandle {
+                    last_key,
+                    offset,
+                    len,
+                }),
+                _ => return Err(corrupted(id, "bad index")),
+            }
+        }
+        let largest = match index.last() {
+            Some(handle) => handle.last_key.clone(),
+            None => return Err(corrupted(id, "empty table")),
+        };
+
+        Ok(Table {
+            id,
+            smallest,
+            largest,
+            size,
+            file,
+            index,
+        })
+    }
+
+    /// Looks up `key`, returning `Some(None)` if the table holds a tombstone for it.
+    pub fn get(&self, key: &str) -> Result<Option<Option<String>>> {
+        if key < self.smallest.as_str() || key > self.largest.as_str() {
+            return Ok(None);
+        }
+        let block = self
+            .index
+            .partition_point(|handle| handle.last_key.as_str() < key);
+        if block == self.index.len() {
+            return Ok(None);
+        }
+        Ok(self
+            .read_entries(block)?
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value))
+    }
+
+    /// Returns `true` if the table's key range intersects `[smallest, largest]`.
+    pub fn overlaps(&self, smallest: &str, largest: &str) -> bool {
+        self.smallest.as_str() <= largest && smallest <= self.largest.as_str()
+    }
+
+    /// Iterates over all entries in key order.
+    pub fn iter(&self) -> TableIter<'_> {
+        self.iter_from("")
+    }
+
+    /// Iterates over the entries in key order, starting with the block that
+    /// may hold `start`. Smaller keys of that block are still returned.
+    pub fn iter_from(&self, start: &str) -> TableIter<'_> {
+        TableIter {
+            table: self,
+            block: self.index.partition_point(|handle| handle.last_key.as_str() < start),
+            entries: Vec::new().into_iter(),
+        }
+    }
+
+    fn read_entries(&self, block: usize) -> Result<Vec<Entry>> {
+        let handle = &self.index[block];
+        let data =
+            read_block(&self.file, handle.offset, handle.len).map_err(|e| with_id(e, self.id))?;
+        let mut buf = &data[..];
+        let mut entries = Vec::new();
+        while buf.has_remaining() {
+            entries.push(get_entry(&mut buf).ok_or_else(|| corrupted(self.id, "bad entry"))?);
+        }
+        Ok(entries)
+    }
+}
+
+/// Sequential iterator over the entries of a `Table`.
+pub(super) struct TableIter<'a> {
+    table: &'a Table,
+    block: usize,
+    entries: std::vec::IntoIter<Entry>,
+}
+
+impl Iterator for TableIter<'_> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            if self.block >= self.table.index.len() {
+                return None;
+            }
+            match self.table.read_entries(self.block) {
+                Ok(entries) => self.entries = entries.into_iter(),
+                Err(e) => {
+                    self.block = self.table.index.len();
+                    return Some(Err(e));
+                }
+            }
+            self.block += 1;
+        }
+    }
+}
+
+fn write_block(writer: &mut impl Write, block: &[u8]) -> Result<u32> {
+    writer.write_all(block)?;
+    writer.write_all(&crc32fast::hash(block).to_be_bytes())?;
+    Ok(block.len() as u32 + 4)
+}
+
+fn read_block(mut file: &File, offset: u64, len: u32) -> Result<Vec<u8>> {
+    if len < 4 {
+        return Err(KvsError::Corruption("block too short".to_owned()));
+    }
+    let mut buf = vec![0; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+
+    let crc = (&buf[buf.len() - 4..]).get_u32();
+    buf.truncate(buf.len() - 4);
+    if crc32fast::hash(&buf) != crc {
+        return Err(KvsError::Corruption("block checksum mismatch".to_owned()));
+    }
+    Ok(buf)
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    buf.put_u32(s.len() as u32);
+    buf.put_slice(s.as_bytes());
+}
+
+fn get_u32(buf: &mut &[u8]) -> Option<u32> {
+    (buf.remaining() >= 4).then(|| buf.get_u32())
+}
+
+fn get_u64(buf: &mut &[u8]) -> Option<u64> {
+    (buf.remaining() >= 8).then(|| buf.get_u64())
+}
+
+fn get_str(buf: &mut &[u8]) -> Option<String> {
+    let len = get_u32(buf)? as usize;
+    if buf.remaining() < len {
+        return None;
+    }
+    let s = String::from_utf8(buf[..len].to_vec()).ok()?;
+    buf.advance(len);
+    Some(s)
+}
+
+fn get_entry(buf: &mut &[u8]) -> Option<Entry> {
+    let key = get_str(buf)?;
+    if !buf.has_remaining() {
+        return None;
+    }
+    match buf.get_u8() {
+        KIND_VALUE => Some((key, Some(get_str(buf)?))),
+        KIND_TOMBSTONE => Some((key, None)),
+        _ => None,
+    }
+}
+
+fn corrupted(id: u64, what: &str) -> KvsError {
+    KvsError::Corruption(format!("table {}: {}", id, what))
+}
+
+fn with_id(err: KvsError, id: u64) -> KvsError {
+    match err {
+        KvsError::Corruption(what) => corrupted(id, &what),
+        err => err,
+    }
+}
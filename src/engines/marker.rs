@@ -0,0 +1,70 @@
+use std::{
+    fs, io,
+    path::Path,
+};
+
+use crate::error::Result;
+
+/// Name of the file recording which engine created a data directory.
+pub const ENGINE_MARKER : &str = "engine";
+
+/// Returns the engine recorded in the marker file of `dir`, if any.
+pub fn read_engine_marker(dir : &Path) -> Result<Option<String>> {
+    match fs::read_to_string(dir.join(ENGINE_MARKER)) {
+        Ok(engine) => Ok(Some(engine.trim().to_owned())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Records `engine` as the engine of `dir`. Only write it once the engine
+/// opened the directory, so a failed open leaves no wrong marker behind.
+pub fn write_engine_marker(dir : &Path, engine : &str) -> Result<()> {
+    fs::write(dir.join(ENGINE_MARKER), engine)?;
+    Ok(())
+}
+
+/// Returns the engine that created `dir`: the one in its marker, or for a
+/// directory from before markers existed, the one whose files it holds.
+/// `None` means `dir` holds no engine's data yet.
+pub fn detect_engine(dir : &Path) -> Result<Option<String>> {
+    if let Some(engine) = read_engine_marker(dir)? {
+        return Ok(Some(engine));
+    }
+
+    let mut found = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let names = entries
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<Vec<_>>>()?;
+    let has = |name : &str| names.iter().any(|n| n == name);
+    let has_numbered = |ext : &str| {
+        names.iter().any(|n| {
+            n.strip_suffix(ext)
+                .is_some_and(|stem| !stem.is_empty() && stem.bytes().all(|b| b.is_ascii_digit()))
+        })
+    };
+    if has_numbered(".log") {
+        found.push("kvs");
+    }
+    if has("conf") && has("db") {
+        found.push("sled");
+    }
+    if has("MANIFEST") || has("sst") || has("wal") || has_numbered(".sst") {
+        found.push("lsm");
+    }
+
+    match found[..] {
+        [] => Ok(None),
+        [engine] => Ok(Some(engine.to_owned())),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{:?} holds the files of several engines: {}", dir, found.join(", ")),
+        )
+        .into()),
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+
+use super::{check_namespace_name, KvsEngine, Scan, DEFAULT_NAMESPACE};
+use crate::{KvsError, Result};
+
+/// A non-persistent engine keeping all pairs in a `BTreeMap`.
+///
+/// Nothing touches the disk, so it suits tests and caches that may be
+/// lost on restart.
+#[derive(Clone)]
+pub struct MemoryEngine {
+    namespaces: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl MemoryEngine {
+    /// Creates an empty `MemoryEngine`.
+    pub fn new() -> Self {
+        let mut namespaces = BTreeMap::new();
+        namespaces.insert(DEFAULT_NAMESPACE.to_owned(), BTreeMap::new());
+        MemoryEngine { namespaces }
+    }
+
+    fn namespace(&self, ns: &str) -> Result<&BTreeMap<String, String>> {
+        self.namespaces
+            .get(ns)
+            .ok_or_else(|| KvsError::NamespaceNotFound(ns.to_owned()))
+    }
+
+    fn namespace_mut(&mut self, ns: &str) -> Result<&mut BTreeMap<String, String>> {
+        self.namespaces
+            .get_mut(ns)
+            .ok_or_else(|| KvsError::NamespaceNotFound(ns.to_owned()))
+    }
+}
+
+impl Default for MemoryEngine {
+    fn default() -> Self {
+        MemoryEngine::new()
+    }
+}
+
+impl KvsEngine for MemoryEngine {
+    fn set_in(&mut self, ns: &str, key: String, value: String) -> Result<()> {
+        self.namespace_mut(ns)?.insert(key, value);
+        Ok(())
+    }
+
+    fn get_in(&self, ns: &str, key: String) -> Result<Option<String>> {
+        Ok(self.namespace(ns)?.get(&key).cloned())
+    }
+
+    fn scan_in<'a>(&'a self, ns: &str, prefix: &str) -> Result<Scan<'a>> {
+        let prefix = prefix.to_owned();
+        let pairs = self
+            .namespace(ns)?
+            .range(prefix.clone()..)
+            .take_while(move |(key, _)| key.starts_with(&prefix))
+            .map(|(key, value)| Ok((key.clone(), value.clone())));
+        Ok(Box::new(pairs))
+    }
+
+    fn remove_in(&mut self, ns: &str, key: String) -> Result<()> {
+        self.namespace_mut(ns)?
+            .remove(&key)
+            .map(|_| ())
+            .ok_or(KvsError::KeyNotFound)
+    }
+
+    fn create_namespace(&mut self, ns: &str) -> Result<()> {
+        check_namespace_name(ns)?;
+        if self.namespaces.contains_key(ns) {
+            return Err(KvsError::NamespaceExists(ns.to_owned()));
+        }
+        self.namespaces.insert(ns.to_owned(), BTreeMap::new());
+        Ok(())
+    }
+
+    fn drop_namespace(&mut self, ns: &str) -> Result<()> {
+        check_namespace_name(ns)?;
+        self.namespaces
+            .remove(ns)
+            .map(|_| ())
+            .ok_or_else(|| KvsError::NamespaceNotFound(ns.to_owned()))
+    }
+
+    fn list_namespaces(&self) -> Result<Vec<String>> {
+        Ok(self.namespaces.keys().cloned().collect())
+    }
+}
@@ -1,17 +1,115 @@
 
-use crate::Result;
+use std::path::Path;
+
+use crate::{KvsError, Result};
+
+/// The namespace used by `set`, `get` and `remove`. It always exists.
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// Key/value pairs yielded by `KvsEngine::scan_in`, sorted by key.
+pub type Scan<'a> = Box<dyn Iterator<Item = Result<(String, String)>> + 'a>;
+
+/// The copying part of a backup, returned by `KvsEngine::start_backup`.
+pub type BackupJob = Box<dyn FnOnce() -> Result<()> + Send>;
 
 pub trait KvsEngine {
-    fn set(&mut self, key : String, value :String) -> Result<()>;
+    fn set(&mut self, key : String, value :String) -> Result<()> {
+        self.set_in(DEFAULT_NAMESPACE, key, value)
+    }
+
+    fn get(&self, key : String) -> Result<Option<String>> {
+        self.get_in(DEFAULT_NAMESPACE, key)
+    }
+
+    fn remove(&mut self, key : String) -> Result<()> {
+        self.remove_in(DEFAULT_NAMESPACE, key)
+    }
+
+    fn scan(&self, prefix : &str) -> Result<Scan<'_>> {
+        self.scan_in(DEFAULT_NAMESPACE, prefix)
+    }
+
+    fn set_in(&mut self, ns : &str, key : String, value : String) -> Result<()>;
+
+    fn get_in(&self, ns : &str, key : String) -> Result<Option<String>>;
+
+    fn remove_in(&mut self, ns : &str, key : String) -> Result<()>;
 
-    fn get(&self, key : String) -> Result<Option<String>>;
+    /// Iterates over the pairs of `ns` whose key starts with `prefix`.
+    fn scan_in<'a>(&'a self, ns : &str, prefix : &str) -> Result<Scan<'a>>;
+
+    /// Like `scan_in`, but only yields the keys sorting after `after`, so a
+    /// long scan can be taken in pages.
+    fn scan_after_in<'a>(&'a self, ns : &str, prefix : &str, after : &str) -> Result<Scan<'a>> {
+        let after = after.to_owned();
+        let pairs = self
+            .scan_in(ns, prefix)?
+            .skip_while(move |pair| pair.as_ref().is_ok_and(|(key, _)| *key <= after));
+        Ok(Box::new(pairs))
+    }
+
+    /// Creates an empty namespace, failing if it already exists.
+    fn create_namespace(&mut self, ns : &str) -> Result<()>;
+
+    /// Deletes a namespace and all of its keys.
+    fn drop_namespace(&mut self, ns : &str) -> Result<()>;
+
+    /// Returns the names of all namespaces, sorted.
+    fn list_namespaces(&self) -> Result<Vec<String>>;
+
+    /// Makes every write so far durable, whatever the engine's sync policy.
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Starts a backup into `dir`, an incremental one if `parent` is given.
+    /// Only what must see the store at one instant runs here; the returned
+    /// job copies the data and can run once the store is free to serve
+    /// other requests again.
+    fn start_backup(&mut self, _dir : &Path, parent : Option<&Path>) -> Result<BackupJob> {
+        let what = if parent.is_some() { "incremental backup" } else { "backup" };
+        Err(KvsError::Unsupported(what.to_owned()))
+    }
+
+    /// Writes a consistent copy of the store into the empty or missing
+    /// directory `dir`, which can then be opened like the original.
+    fn backup_to(&mut self, dir : &Path) -> Result<()> {
+        self.start_backup(dir, None)?()
+    }
+
+    /// Writes into `dir` only what changed since the backup in `parent`,
+    /// which may itself be incremental. See `restore_dir`.
+    fn backup_incremental_to(&mut self, dir : &Path, parent : &Path) -> Result<()> {
+        self.start_backup(dir, Some(parent))?()
+    }
+}
 
-    fn remove(&mut self, key : String) -> Result<()>;
+/// Rejects names that cannot be created or dropped.
+fn check_namespace_name(ns : &str) -> Result<()> {
+    if ns.is_empty() || ns == DEFAULT_NAMESPACE {
+        return Err(KvsError::InvalidNamespace(ns.to_owned()));
+    }
+    Ok(())
 }
 
+pub mod check;
 pub mod kvs;
+pub mod lsm;
+pub mod marker;
+pub mod memory;
+pub mod repair;
+pub mod restore;
 pub mod sled;
+pub mod vfs;
 pub mod wal;
 
-pub use self::kvs::KvStore;
-pub use self::sled::SledKvsEngine;
\ No newline at end of file
+pub use self::check::{check_dir, CheckReport, SegmentReport, SegmentStatus};
+pub use self::kvs::{BackupManifest, KvStore, BACKUP_MANIFEST};
+pub use self::lsm::{LsmEngine, LsmOptions};
+pub use self::marker::{detect_engine, read_engine_marker, write_engine_marker, ENGINE_MARKER};
+pub use self::memory::MemoryEngine;
+pub use self::repair::{repair_dir, DamagedRange, RepairReport, SegmentRepair, QUARANTINE_DIR};
+pub use self::restore::{restore_dir, RestorePoint, RestoreReport};
+pub use self::sled::SledKvsEngine;
+pub use self::vfs::{FileSystem, FsFile, OsFileSystem};
+pub use self::wal::{LogEnd, RecordPos, Replay, SegmentCopy, SyncPolicy, Wal};
\ No newline at end of file
@@ -0,0 +1,180 @@
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use super::kvs::Command;
+use super::vfs::{FileSystem, OsFileSystem};
+use super::wal::{log_path, salvage_segment, sorted_gen_list, write_segment};
+use crate::error::Result;
+
+/// Directory under the data directory that receives unreadable data.
+pub const QUARANTINE_DIR : &str = "quarantine";
+
+/// What `repair_dir` salvaged and lost.
+#[derive(Debug, Clone, Serialize)]
+pub struct RepairReport {
+    pub dir : PathBuf,
+    /// `false` if no damage was found and nothing was changed.
+    pub repaired : bool,
+    /// Generation now holding the salvaged records.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub salvaged_gen : Option<u64>,
+    /// Where the unreadable ranges and this report were written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quarantine_dir : Option<PathBuf>,
+    pub salvaged_records : u64,
+    pub lost_bytes : u64,
+    pub segments : Vec<SegmentRepair>,
+}
+
+/// Repair findings for one `<gen>.log` segment.
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentRepair {
+    pub gen : u64,
+    pub size : u64,
+    pub salvaged_records : u64,
+    pub damaged : Vec<DamagedRange>,
+}
+
+/// A byte range of a segment that held no intact record.
+#[derive(Debug, Clone, Serialize)]
+pub struct DamagedRange {
+    pub offset : u64,
+    pub len : u64,
+    /// Copy of the raw bytes.
+    pub file : PathBuf,
+    /// Keys whose commands appear to have been in the range. A best-effort
+    /// guess from the readable fragments, not a complete list.
+    pub possible_keys : Vec<String>,
+}
+
+/// Salvages a `KvStore` directory whose log is damaged.
+///
+/// Every segment is scanned, resynchronizing on record boundaries after
+/// damage. Starting at the first damaged segment, the intact records are
+/// rewritten in order into a fresh generation and the damaged segments are
+/// deleted. Unreadable ranges, including records that pass their checksum
+/// but cannot be decoded, are copied into a new directory under
+/// `QUARANTINE_DIR` together with a `summary.json` of this report.
+pub fn repair_dir(dir : &Path) -> Result<RepairReport> {
+    let fs = OsFileSystem;
+    let gens = sorted_gen_list(&fs, dir)?;
+
+    let mut salvages = Vec::new();
+    for &gen in &gens {
+        let mut salvage = salvage_segment(&fs, dir, gen)?;
+        let mut records = Vec::new();
+        for (pos, payload) in salvage.records {
+            if serde_json::from_slice::<Command>(&payload).is_ok() {
+                records.push(payload);
+            } else {
+                salvage.damaged.push(pos.pos..pos.pos + pos.len);
+            }
+        }
+        salvage.damaged.sort_by_key(|range| range.start);
+        salvages.push((gen, records, salvage.damaged));
+    }
+
+    let mut report = RepairReport {
+        dir : dir.to_owned(),
+        repaired : false,
+        salvaged_gen : None,
+        quarantine_dir : None,
+        salvaged_records : 0,
+        lost_bytes : 0,
+        segments : Vec::new(),
+    };
+    let first_damaged = match salvages.iter().position(|(_, _, damaged)| !damaged.is_empty()) {
+        Some(first_damaged) => first_damaged,
+        None => return Ok(report),
+    };
+    // segments before the first damaged one are left alone
+    let salvages = &salvages[first_damaged..];
+
+    let quarantine = new_quarantine_dir(dir)?;
+    for (gen, records, damaged) in salvages {
+        let data = fs::read(log_path(dir, *gen))?;
+        let mut ranges = Vec::new();
+        for range in damaged {
+            ranges.push(quarantine_range(&quarantine, *gen, &data, range.clone())?);
+        }
+        report.salvaged_records += records.len() as u64;
+        report.lost_bytes += ranges.iter().map(|range| range.len).sum::<u64>();
+        report.segments.push(SegmentRepair {
+            gen : *gen,
+            size : data.len() as u64,
+            salvaged_records : records.len() as u64,
+            damaged : ranges,
+        });
+    }
+
+    let salvaged_gen = gens.last().map_or(1, |last| last + 1);
+    let payloads = salvages
+        .iter()
+        .flat_map(|(_, records, _)| records)
+        .map(|payload| payload.as_slice());
+    write_segment(&fs, dir, salvaged_gen, payloads)?;
+    // Newest first: if interrupted, the leftovers are a prefix of what was
+    // salvaged, and replaying a prefix again before it changes nothing.
+    for (gen, _, _) in salvages.iter().rev() {
+        fs.remove_file(&log_path(dir, *gen))?;
+    }
+
+    report.repaired = true;
+    report.salvaged_gen = Some(salvaged_gen);
+    report.quarantine_dir = Some(quarantine.clone());
+    fs::write(quarantine.join("summary.json"), serde_json::to_vec_pretty(&report)?)?;
+    Ok(report)
+}
+
+fn new_quarantine_dir(dir : &Path) -> Result<PathBuf> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let mut path = dir.join(QUARANTINE_DIR).join(secs.to_string());
+    let mut attempt = 1;
+    while path.exists() {
+        path = dir.join(QUARANTINE_DIR).join(format!("{}-{}", secs, attempt));
+        attempt += 1;
+    }
+    fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+fn quarantine_range(quarantine : &Path, gen : u64, data : &[u8], range : Range<u64>) -> Result<DamagedRange> {
+    let bytes = &data[range.start as usize..range.end as usize];
+    let file = quarantine.join(format!("{}-{}.bin", gen, range.start));
+    fs::write(&file, bytes)?;
+    Ok(DamagedRange {
+        offset : range.start,
+        len : range.end - range.start,
+        file,
+        possible_keys : possible_keys(bytes),
+    })
+}
+
+/// Picks the values of `"key"` fields out of damaged JSON commands.
+fn possible_keys(bytes : &[u8]) -> Vec<String> {
+    const FIELD : &str = "\"key\":\"";
+    let text = String::from_utf8_lossy(bytes);
+    let mut keys = Vec::new();
+    let mut rest = text.as_ref();
+    while let Some(start) = rest.find(FIELD) {
+        rest = &rest[start + FIELD.len()..];
+        match rest.find('"') {
+            Some(end) => {
+                keys.push(rest[..end].to_owned());
+                rest = &rest[end..];
+            }
+            None => break,
+        }
+    }
+    keys.sort();
+    keys.dedup();
+    keys
+}
@@ -0,0 +1,158 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use super::kvs::BackupManifest;
+use super::vfs::{FileSystem, OsFileSystem};
+use super::wal::{check_segment, log_path, SegmentCopy};
+use crate::error::{KvsError, Result};
+
+/// How much of a chain of backups `restore_dir` replays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestorePoint {
+    /// Everything in the chain.
+    Latest,
+    /// Up to and including the record with this sequence number.
+    Seq(u64),
+    /// Up to the last backup taken at or before this many seconds since the
+    /// Unix epoch. Records carry no timestamps, so a backup is the finest
+    /// granularity.
+    Time(u64),
+}
+
+/// What `restore_dir` rebuilt.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreReport {
+    pub dir : PathBuf,
+    /// Number of backups replayed, including a partly replayed last one.
+    pub backups : usize,
+    /// Sequence number of the last restored record.
+    pub seq : u64,
+    /// When the last replayed backup was taken.
+    pub created_at : u64,
+}
+
+/// Rebuilds a `KvStore` directory in the empty or missing `dir` from a full
+/// backup followed by incremental backups, oldest first.
+///
+/// Each incremental backup must continue the one before it; a gap in the
+/// chain is reported as an error before anything is written.
+pub fn restore_dir(dir : &Path, backups : &[PathBuf], point : RestorePoint) -> Result<RestoreReport> {
+    let mut chain : Vec<(&PathBuf, BackupManifest)> = Vec::new();
+    for backup in backups {
+        let manifest = BackupManifest::read(backup)?;
+        match (chain.last(), manifest.since) {
+            (None, None) => {}
+            (None, Some(_)) => {
+                return Err(KvsError::Backup(format!(
+                    "{:?} is incremental, the chain must start with a full backup",
+                    backup
+                )))
+            }
+            (Some(_), None) => {
+                return Err(KvsError::Backup(format!(
+                    "{:?} is a full backup, only the first one can be",
+                    backup
+                )))
+            }
+            (Some((parent, prev)), Some(since)) => {
+                if since != prev.end() || manifest.start_seq != prev.end_seq {
+                    return Err(KvsError::Backup(format!(
+                        "{:?} does not continue {:?}",
+                        backup, parent
+                    )));
+                }
+            }
+        }
+        chain.push((backup, manifest));
+    }
+
+    let last_seq = match (point, chain.last()) {
+        (_, None) => return Err(KvsError::Backup("no backup given".to_owned())),
+        (RestorePoint::Latest, Some((_, last))) => last.end_seq,
+        (RestorePoint::Seq(seq), Some((_, last))) => {
+            if seq > last.end_seq {
+                return Err(KvsError::Backup(format!(
+                    "sequence number {} is past the last backup, which ends at {}",
+                    seq, last.end_seq
+                )));
+            }
+            seq
+        }
+        (RestorePoint::Time(time), Some(_)) => {
+            let kept = chain.iter().take_while(|(_, manifest)| manifest.created_at <= time).count();
+            chain.truncate(kept);
+            match chain.last() {
+                Some((_, last)) => last.end_seq,
+                None => {
+                    return Err(KvsError::Backup(format!(
+                        "the full backup was taken after {}",
+                        time
+                    )))
+                }
+            }
+        }
+    };
+
+    let fs = OsFileSystem;
+    fs.create_dir_all(dir)?;
+    if !fs.list_files(dir)?.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{:?} is not empty", dir)).into());
+    }
+
+    let mut report = RestoreReport {
+        dir : dir.to_owned(),
+        backups : 0,
+        seq : 0,
+        created_at : 0,
+    };
+    for (backup, manifest) in &chain {
+        if report.backups > 0 && report.seq == last_seq {
+            break;
+        }
+        for segment in &manifest.segments {
+            let len = if manifest.since.is_none() {
+                segment.len
+            } else {
+                // keep whole records until the restore point is reached
+                let mut ends = Vec::new();
+                let check = check_segment(&fs, backup, segment.gen, |pos, _| ends.push(pos.pos + pos.len))?;
+                if let Some(error) = check.error {
+                    return Err(KvsError::Corruption(format!(
+                        "{:?}: {}",
+                        log_path(backup, segment.gen),
+                        error
+                    )));
+                }
+                let records = ends.len().min((last_seq - report.seq) as usize);
+                report.seq += records as u64;
+                records.checked_sub(1).map_or(0, |last| ends[last])
+            };
+            append_segment(&fs, backup, dir, segment, len)?;
+        }
+        report.backups += 1;
+        report.created_at = manifest.created_at;
+    }
+    fs.sync_dir(dir)?;
+    Ok(report)
+}
+
+/// Appends the first `len` bytes of the copy of `segment` in `backup` to the
+/// same segment in `dir`, which must end where the copy starts.
+fn append_segment(fs : &dyn FileSystem, backup : &Path, dir : &Path, segment : &SegmentCopy, len : u64) -> Result<()> {
+    let mut file = fs.open_write(&log_path(dir, segment.gen))?;
+    let size = file.size()?;
+    if size != segment.offset {
+        return Err(KvsError::Backup(format!(
+            "segment {} of {:?} starts at {} but the restored one ends at {}",
+            segment.gen, backup, segment.offset, size
+        )));
+    }
+    file.seek(SeekFrom::End(0))?;
+    io::copy(&mut fs.open_read(&log_path(backup, segment.gen))?.take(len), &mut file)?;
+    file.sync_data()?;
+    Ok(())
+}
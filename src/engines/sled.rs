@@ -1,39 +1,136 @@
-use super::KvsEngine;
+use super::wal::SyncPolicy;
+use super::{check_namespace_name, KvsEngine, Scan, DEFAULT_NAMESPACE};
 use crate::{KvsError, Result};
 use sled::{Db, Tree};
+use std::path::Path;
 
-/// Wrapper of `sled::Db`
+/// Wrapper of `sled::Db`. Namespaces other than the default one are
+/// stored in separate sled `Tree`s.
 #[derive(Clone)]
-pub struct SledKvsEngine(Db);
+pub struct SledKvsEngine {
+    db: Db,
+    flush_every_write: bool,
+}
 
 impl SledKvsEngine {
-    /// Creates a `SledKvsEngine` from `sled::Db`.
+    /// Creates a `SledKvsEngine` from `sled::Db`, flushing after every write.
     pub fn new(db: Db) -> Self {
-        SledKvsEngine(db)
+        SledKvsEngine {
+            db,
+            flush_every_write: true,
+        }
+    }
+
+    /// Opens the sled database at the given path, flushing after every write.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        SledKvsEngine::open_with_sync(path, SyncPolicy::Always)
+    }
+
+    /// Opens the sled database at the given path.
+    ///
+    /// `SyncPolicy::Interval` leaves flushing to sled's background flusher
+    /// and `SyncPolicy::Never` only flushes when the database is dropped.
+    pub fn open_with_sync(path: impl AsRef<Path>, sync: SyncPolicy) -> Result<Self> {
+        let flush_every_ms = match sync {
+            SyncPolicy::Interval(interval) => Some(interval.as_millis() as u64),
+            SyncPolicy::Always | SyncPolicy::Never => None,
+        };
+        let db = sled::Config::new()
+            .path(path)
+            .flush_every_ms(flush_every_ms)
+            .open()?;
+        Ok(SledKvsEngine {
+            db,
+            flush_every_write: sync == SyncPolicy::Always,
+        })
+    }
+
+    fn flush(&self) -> Result<()> {
+        if self.flush_every_write {
+            self.db.flush()?;
+        }
+        Ok(())
+    }
+
+    fn has_tree(&self, ns: &str) -> bool {
+        self.db.tree_names().iter().any(|name| name == ns.as_bytes())
+    }
+
+    /// Returns the tree of `ns` without implicitly creating it.
+    fn tree(&self, ns: &str) -> Result<Tree> {
+        if ns == DEFAULT_NAMESPACE {
+            return Ok(Tree::clone(&self.db));
+        }
+        if !self.has_tree(ns) {
+            return Err(KvsError::NamespaceNotFound(ns.to_owned()));
+        }
+        Ok(self.db.open_tree(ns)?)
     }
 }
 
 impl KvsEngine for SledKvsEngine {
-    fn set(&mut self, key: String, value: String) -> Result<()> {
-        let tree: &Tree = &self.0;
-        tree.insert(key, value.into_bytes()).map(|_| ());
-        tree.flush();
-        Ok(())
+    fn set_in(&mut self, ns: &str, key: String, value: String) -> Result<()> {
+        let tree = self.tree(ns)?;
+        tree.insert(key, value.into_bytes())?;
+        self.flush()
     }
 
-    fn get(&self, key: String) -> Result<Option<String>> {
-        let tree: &Tree = &self.0;
+    fn get_in(&self, ns: &str, key: String) -> Result<Option<String>> {
+        let tree = self.tree(ns)?;
         Ok(tree
-            .get(key).unwrap()
+            .get(key)?
             .map(|i_vec| AsRef::<[u8]>::as_ref(&i_vec).to_vec())
             .map(String::from_utf8)
-            .transpose().unwrap())
+            .transpose()?)
+    }
+
+    fn scan_in<'a>(&'a self, ns: &str, prefix: &str) -> Result<Scan<'a>> {
+        let pairs = self.tree(ns)?.scan_prefix(prefix).map(|item| {
+            let (key, value) = item?;
+            Ok((String::from_utf8(key.to_vec())?, String::from_utf8(value.to_vec())?))
+        });
+        Ok(Box::new(pairs))
+    }
+
+    fn remove_in(&mut self, ns: &str, key: String) -> Result<()> {
+        let tree = self.tree(ns)?;
+        tree.remove(key)?.ok_or(KvsError::KeyNotFound)?;
+        self.flush()
+    }
+
+    fn create_namespace(&mut self, ns: &str) -> Result<()> {
+        check_namespace_name(ns)?;
+        if self.has_tree(ns) {
+            return Err(KvsError::NamespaceExists(ns.to_owned()));
+        }
+        self.db.open_tree(ns)?;
+        self.flush()
+    }
+
+    fn drop_namespace(&mut self, ns: &str) -> Result<()> {
+        check_namespace_name(ns)?;
+        if !self.db.drop_tree(ns)? {
+            return Err(KvsError::NamespaceNotFound(ns.to_owned()));
+        }
+        self.flush()
     }
 
-    fn remove(&mut self, key: String) -> Result<()> {
-        let tree: &Tree = &self.0;
-        tree.remove(key).unwrap().ok_or(KvsError::KeyNotFound)?;
-        tree.flush();
+    fn list_namespaces(&self) -> Result<Vec<String>> {
+        let default_tree = Tree::clone(&self.db);
+        let mut names = self
+            .db
+            .tree_names()
+            .into_iter()
+            .filter(|name| *name != default_tree.name())
+            .map(|name| Ok(String::from_utf8(name.to_vec())?))
+            .collect::<Result<Vec<_>>>()?;
+        names.push(DEFAULT_NAMESPACE.to_owned());
+        names.sort();
+        Ok(names)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.db.flush()?;
         Ok(())
     }
-}
\ No newline at end of file
+}
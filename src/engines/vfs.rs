@@ -0,0 +1,117 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+/// The file operations the storage engines need from a filesystem.
+///
+/// `OsFileSystem` forwards to `std::fs`; tests can substitute an
+/// implementation that loses unsynced data or fails on demand.
+pub trait FileSystem: Send + Sync {
+    fn create_dir_all(&self, path : &Path) -> io::Result<()>;
+
+    /// Returns the paths of the regular files in `dir`.
+    fn list_files(&self, dir : &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Opens an existing file for reading.
+    fn open_read(&self, path : &Path) -> io::Result<Box<dyn FsFile>>;
+
+    /// Opens a file for reading and writing, creating it if needed.
+    fn open_write(&self, path : &Path) -> io::Result<Box<dyn FsFile>>;
+
+    fn remove_file(&self, path : &Path) -> io::Result<()>;
+
+    /// Creates `to` as another name for `from`, if the filesystem can.
+    fn hard_link(&self, _from : &Path, _to : &Path) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Atomically replaces `to` with `from`.
+    fn rename(&self, from : &Path, to : &Path) -> io::Result<()>;
+
+    /// Makes the creation and removal of files in `dir` durable.
+    fn sync_dir(&self, dir : &Path) -> io::Result<()>;
+}
+
+/// An open file of a `FileSystem`.
+pub trait FsFile: Read + Write + Seek + Send {
+    fn size(&self) -> io::Result<u64>;
+
+    fn set_len(&mut self, len : u64) -> io::Result<()>;
+
+    /// Makes everything written so far durable.
+    fn sync_data(&mut self) -> io::Result<()>;
+}
+
+/// The real filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsFileSystem;
+
+impl FileSystem for OsFileSystem {
+    fn create_dir_all(&self, path : &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn list_files(&self, dir : &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+
+    fn open_read(&self, path : &Path) -> io::Result<Box<dyn FsFile>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn open_write(&self, path : &Path) -> io::Result<Box<dyn FsFile>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn remove_file(&self, path : &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn hard_link(&self, from : &Path, to : &Path) -> io::Result<()> {
+        fs::hard_link(from, to)
+    }
+
+    fn rename(&self, from : &Path, to : &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    #[cfg(unix)]
+    fn sync_dir(&self, dir : &Path) -> io::Result<()> {
+        File::open(dir)?.sync_all()
+    }
+
+    // directories cannot be opened as files elsewhere
+    #[cfg(not(unix))]
+    fn sync_dir(&self, _dir : &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FsFile for File {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn set_len(&mut self, len : u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+
+    fn sync_data(&mut self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+}
@@ -0,0 +1,701 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    ffi::OsStr,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::Buf;
+use serde::{Deserialize, Serialize};
+
+use super::vfs::{FileSystem, FsFile, OsFileSystem};
+use crate::{KvsError, Result};
+
+const HEADER_LEN: u64 = 8;
+
+type FileReader = BufReaderWithPos<Box<dyn FsFile>>;
+type FileWriter = BufWriteWithPos<Box<dyn FsFile>>;
+
+/// When appended records are fsynced to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Only hand records to the OS; a machine crash may lose recent writes.
+    Never,
+    /// Sync after every append.
+    Always,
+    /// Sync on append if the last sync is older than the interval.
+    Interval(Duration),
+}
+
+/// Location of a framed record inside the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordPos {
+    pub gen: u64,
+    pub pos: u64,
+    pub len: u64,
+}
+
+/// End of the written part of the log.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEnd {
+    pub gen: u64,
+    pub pos: u64,
+}
+
+/// A segment copied by `SealedSegments::copy_to`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentCopy {
+    pub gen: u64,
+    /// Offset in the original segment the copy starts at.
+    #[serde(default)]
+    pub offset: u64,
+    pub len: u64,
+    /// Whether the copy is a hard link to the original.
+    pub linked: bool,
+}
+
+/// A segmented write-ahead log.
+///
+/// Each segment is a `<gen>.log` file in the log directory holding records
+/// framed as `[len: u32][crc32: u32][payload]`, the checksum covering both
+/// the length and the payload. Appends always go to the segment with the
+/// highest generation; opening the log starts a new one.
+///
+/// After a failed write or sync the state of the active segment is unknown,
+/// so every later append fails until the log is reopened.
+pub struct Wal {
+    dir: PathBuf,
+    fs: Arc<dyn FileSystem>,
+    sync: SyncPolicy,
+    readers: RefCell<HashMap<u64, FileReader>>,
+    writer: FileWriter,
+    gen: u64,
+    gens: Vec<u64>,
+    last_sync: Instant,
+    failed: bool,
+}
+
+impl Wal {
+    /// Opens the log in `dir`, creating the directory if needed.
+    ///
+    /// A torn record at the tail of the newest segment is truncated;
+    /// any other damage is reported by `replay`.
+    pub fn open(dir: impl Into<PathBuf>, sync: SyncPolicy) -> Result<Wal> {
+        Wal::open_with_fs(dir, sync, Arc::new(OsFileSystem))
+    }
+
+    /// Opens the log in `dir` on the given filesystem.
+    pub fn open_with_fs(
+        dir: impl Into<PathBuf>,
+        sync: SyncPolicy,
+        fs: Arc<dyn FileSystem>,
+    ) -> Result<Wal> {
+        let dir = dir.into();
+        fs.create_dir_all(&dir)?;
+
+        let mut gens = sorted_gen_list(fs.as_ref(), &dir)?;
+        if let Some(&last) = gens.last() {
+            repair_tail(fs.as_ref(), &dir, last)?;
+        }
+        let gen = gens.last().map_or(1, |last| last + 1);
+        let writer = new_log_file(fs.as_ref(), &dir, gen)?;
+        gens.push(gen);
+
+        Ok(Wal {
+            dir,
+            fs,
+            sync,
+            readers: RefCell::new(HashMap::new()),
+            writer,
+            gen,
+            gens,
+            last_sync: Instant::now(),
+            failed: false,
+        })
+    }
+
+    /// Generation of the segment appends go to.
+    pub fn active_gen(&self) -> u64 {
+        self.gen
+    }
+
+    /// All live generations, oldest first.
+    pub fn generations(&self) -> &[u64] {
+        &self.gens
+    }
+
+    /// Appends a record to the active segment and flushes it to the OS.
+    pub fn append(&mut self, payload: &[u8]) -> Result<RecordPos> {
+        self.check_usable()?;
+        let pos = self.writer.pos;
+        let written = write_record(&mut self.writer, payload);
+        self.fail_on_error(written)?;
+
+        match self.sync {
+            SyncPolicy::Never => {}
+            SyncPolicy::Always => self.sync()?,
+            SyncPolicy::Interval(interval) => {
+                if self.last_sync.elapsed() >= interval {
+                    self.sync()?;
+                }
+            }
+        }
+
+        Ok(RecordPos {
+            gen: self.gen,
+            pos,
+            len: self.writer.pos - pos,
+        })
+    }
+
+    /// Flushes buffered records and fsyncs the active segment.
+    pub fn sync(&mut self) -> Result<()> {
+        self.check_usable()?;
+        let synced = self
+            .writer
+            .flush()
+            .and_then(|_| self.writer.writer.get_mut().sync_data());
+        self.fail_on_error(synced)?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Reads the payload of the record at `pos`.
+    pub fn read(&self, pos: &RecordPos) -> Result<Vec<u8>> {
+        let mut readers = self.readers.borrow_mut();
+        let reader = match readers.entry(pos.gen) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let file = self.fs.open_read(&log_path(&self.dir, pos.gen))?;
+                entry.insert(BufReaderWithPos::new(file)?)
+            }
+        };
+        reader.seek(SeekFrom::Start(pos.pos))?;
+        match read_record(reader, pos.gen, pos.pos + pos.len)? {
+            Some((_, payload)) => Ok(payload),
+            None => Err(corrupted(pos.gen, pos.pos, "record out of range")),
+        }
+    }
+
+    /// Iterates over the records of all segments from `from_gen` on.
+    pub fn replay(&self, from_gen: u64) -> Replay {
+        Replay {
+            dir: self.dir.clone(),
+            fs: Arc::clone(&self.fs),
+            gens: self
+                .gens
+                .iter()
+                .copied()
+                .filter(|&gen| gen >= from_gen)
+                .collect::<Vec<_>>()
+                .into_iter(),
+            current: None,
+        }
+    }
+
+    /// Seals the active segment and starts a new one. Returns the new generation.
+    pub fn roll(&mut self) -> Result<u64> {
+        self.sync()?;
+        self.writer = new_log_file(self.fs.as_ref(), &self.dir, self.gen + 1)?;
+        self.gen += 1;
+        self.gens.push(self.gen);
+        Ok(self.gen)
+    }
+
+    /// Deletes all segments older than `gen`. The active segment is never deleted.
+    ///
+    /// Segments are deleted oldest first. If one cannot be deleted the newer
+    /// ones are kept as well, since replaying a segment without the ones
+    /// after it could bring back removed keys; the next call retries.
+    pub fn truncate_before(&mut self, gen: u64) -> Result<()> {
+        let gen = gen.min(self.gen);
+        while let Some(&stale_gen) = self.gens.first().filter(|&&g| g < gen) {
+            self.readers.get_mut().remove(&stale_gen);
+            if let Err(e) = self.fs.remove_file(&log_path(&self.dir, stale_gen)) {
+                log::error!("{:?} cannot be deleted: {}", log_path(&self.dir, stale_gen), e);
+                break;
+            }
+            self.gens.remove(0);
+        }
+        Ok(())
+    }
+
+    /// Seals the active segment, so that no segment written so far changes
+    /// again, and returns them to be copied by `SealedSegments::copy_to`:
+    /// all of them, or only the rest of segment `since.gen` and newer ones.
+    ///
+    /// Fails if segment `since.gen` no longer holds `since.pos` bytes, as
+    /// after a compaction, because records would be missing from the copy.
+    /// The caller must not `truncate_before` the sealed segments until they
+    /// are copied.
+    pub fn seal(&mut self, since: Option<LogEnd>) -> Result<SealedSegments> {
+        if let Some(since) = since {
+            if !self.gens.contains(&since.gen) || self.segment_len(since.gen)? < since.pos {
+                return Err(KvsError::Backup(format!(
+                    "the log no longer holds the first {} bytes of segment {}",
+                    since.pos, since.gen
+                )));
+            }
+        }
+        self.roll()?;
+        let gens = self
+            .gens
+            .iter()
+            .copied()
+            .filter(|&gen| gen != self.gen && since.is_none_or(|since| gen >= since.gen))
+            .collect();
+        Ok(SealedSegments {
+            dir: self.dir.clone(),
+            fs: Arc::clone(&self.fs),
+            gens,
+            since,
+        })
+    }
+
+    /// End of the records appended so far.
+    pub fn end(&self) -> LogEnd {
+        LogEnd { gen: self.gen, pos: self.writer.pos }
+    }
+
+    /// Length of segment `gen`, up to the last append for the active one.
+    fn segment_len(&self, gen: u64) -> Result<u64> {
+        if gen == self.gen {
+            return Ok(self.writer.pos);
+        }
+        Ok(self.fs.open_read(&log_path(&self.dir, gen))?.size()?)
+    }
+
+    fn check_usable(&self) -> Result<()> {
+        if self.failed {
+            return Err(io::Error::other("log is unusable after a failed write").into());
+        }
+        Ok(())
+    }
+
+    fn fail_on_error<T>(&mut self, result: io::Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.failed = true;
+        }
+        Ok(result?)
+    }
+}
+
+/// Segments sealed by `Wal::seal`, which can be copied while appends go on
+/// to a newer one.
+pub struct SealedSegments {
+    dir: PathBuf,
+    fs: Arc<dyn FileSystem>,
+    gens: Vec<u64>,
+    since: Option<LogEnd>,
+}
+
+impl SealedSegments {
+    /// Copies the segments into the empty or missing directory `dir`. They
+    /// are hard-linked where possible, as they never change again.
+    pub fn copy_to(&self, dir: &Path) -> Result<Vec<SegmentCopy>> {
+        self.fs.create_dir_all(dir)?;
+        if !self.fs.list_files(dir)?.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} is not empty", dir),
+            )
+            .into());
+        }
+
+        let mut copies = Vec::new();
+        for &gen in &self.gens {
+            let offset = match self.since {
+                Some(since) if gen == since.gen => since.pos,
+                _ => 0,
+            };
+            let (from, to) = (log_path(&self.dir, gen), log_path(dir, gen));
+            if offset == 0 && self.fs.hard_link(&from, &to).is_ok() {
+                let len = self.fs.open_read(&to)?.size()?;
+                copies.push(SegmentCopy { gen, offset, len, linked: true });
+                continue;
+            }
+            let mut reader = self.fs.open_read(&from)?;
+            let len = reader.size()? - offset;
+            reader.seek(SeekFrom::Start(offset))?;
+            let mut file = self.fs.open_write(&to)?;
+            io::copy(&mut reader.take(len), &mut file)?;
+            file.sync_data()?;
+            copies.push(SegmentCopy { gen, offset, len, linked: false });
+        }
+        self.fs.sync_dir(dir)?;
+        Ok(copies)
+    }
+}
+
+/// Iterator over the records of a `Wal`, yielding each position and payload.
+pub struct Replay {
+    dir: PathBuf,
+    fs: Arc<dyn FileSystem>,
+    gens: std::vec::IntoIter<u64>,
+    current: Option<(u64, u64, FileReader)>,
+}
+
+impl Replay {
+    fn next_record(&mut self) -> Result<Option<(RecordPos, Vec<u8>)>> {
+        loop {
+            if let Some((gen, end, reader)) = &mut self.current {
+                let pos = reader.pos;
+                if let Some((len, payload)) = read_record(reader, *gen, *end)? {
+                    return Ok(Some((RecordPos { gen: *gen, pos, len }, payload)));
+                }
+            }
+            let gen = match self.gens.next() {
+                Some(gen) => gen,
+                None => return Ok(None),
+            };
+            let file = self.fs.open_read(&log_path(&self.dir, gen))?;
+            let end = file.size()?;
+            self.current = Some((gen, end, BufReaderWithPos::new(file)?));
+        }
+    }
+}
+
+impl Iterator for Replay {
+    type Item = Result<(RecordPos, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.next_record().transpose();
+        if let Some(Err(_)) = item {
+            self.current = None;
+            self.gens = Vec::new().into_iter();
+        }
+        item
+    }
+}
+
+/// Reads the record at the current position, returning its framed length
+/// and payload, or `None` at `end`.
+fn read_record<R: Read + Seek>(
+    reader: &mut BufReaderWithPos<R>,
+    gen: u64,
+    end: u64,
+) -> Result<Option<(u64, Vec<u8>)>> {
+    let pos = reader.pos;
+    if pos >= end {
+        return Ok(None);
+    }
+    if end - pos < HEADER_LEN {
+        return Err(corrupted(gen, pos, "truncated header"));
+    }
+    let mut header = [0; HEADER_LEN as usize];
+    reader.read_exact(&mut header)?;
+    let mut header = &header[..];
+    let len = header.get_u32();
+    let crc = header.get_u32();
+    if end - pos - HEADER_LEN < len as u64 {
+        return Err(corrupted(gen, pos, "truncated payload"));
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    if checksum(len, &payload) != crc {
+        return Err(corrupted(gen, pos, "checksum mismatch"));
+    }
+    Ok(Some((HEADER_LEN + len as u64, payload)))
+}
+
+fn write_record<W: Write + Seek>(writer: &mut BufWriteWithPos<W>, payload: &[u8]) -> io::Result<()> {
+    let len = payload.len() as u32;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&checksum(len, payload).to_be_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+// Covering the length keeps zero-filled space from reading as empty records
+fn checksum(len: u32, payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&len.to_be_bytes());
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// What `check_segment` found in a segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentCheck {
+    pub gen: u64,
+    pub size: u64,
+    /// Length of the prefix made of intact records.
+    pub valid_len: u64,
+    /// Description of the first damaged record, if any.
+    pub error: Option<String>,
+    /// Whether the damaged record is the last one, as left by a crash mid-write.
+    pub torn: bool,
+}
+
+/// Reads every record of segment `gen` without modifying it, passing each
+/// intact one to `f`. Checking stops at the first damaged record.
+pub fn check_segment(
+    fs: &dyn FileSystem,
+    dir: &Path,
+    gen: u64,
+    mut f: impl FnMut(RecordPos, &[u8]),
+) -> Result<SegmentCheck> {
+    let data = read_segment(fs, dir, gen)?;
+    let size = data.len() as u64;
+    let mut damaged_at = None;
+    // a crash mid-write leaves no intact record anywhere after the bad one;
+    // a damaged length field can make any record look like it runs to the
+    // end of the file, so only the absence of later records tells a torn
+    // tail from corruption
+    let mut torn = true;
+    scan_records(&data, |span| match span {
+        Span::Record(pos, payload) if damaged_at.is_none() => {
+            let len = HEADER_LEN + payload.len() as u64;
+            f(RecordPos { gen, pos: pos as u64, len }, payload);
+        }
+        Span::Record(..) => torn = false,
+        Span::Damaged(range) => {
+            damaged_at.get_or_insert(range.start);
+        }
+    });
+    Ok(match damaged_at {
+        None => SegmentCheck {
+            gen,
+            size,
+            valid_len: size,
+            error: None,
+            torn: false,
+        },
+        Some(pos) => SegmentCheck {
+            gen,
+            size,
+            valid_len: pos as u64,
+            error: Some(corrupted(gen, pos as u64, damage_at(&data, pos)).to_string()),
+            torn,
+        },
+    })
+}
+
+/// Intact records and damaged byte ranges of a segment.
+#[derive(Debug, Clone, Default)]
+pub struct Salvage {
+    pub records: Vec<(RecordPos, Vec<u8>)>,
+    pub damaged: Vec<Range<u64>>,
+}
+
+/// Reads segment `gen` in full, resynchronizing after damage on the next
+/// offset where an intact record starts.
+pub fn salvage_segment(fs: &dyn FileSystem, dir: &Path, gen: u64) -> Result<Salvage> {
+    let data = read_segment(fs, dir, gen)?;
+    let mut salvage = Salvage::default();
+    scan_records(&data, |span| match span {
+        Span::Record(pos, payload) => {
+            let len = HEADER_LEN + payload.len() as u64;
+            salvage.records.push((RecordPos { gen, pos: pos as u64, len }, payload.to_vec()));
+        }
+        Span::Damaged(range) => salvage.damaged.push(range.start as u64..range.end as u64),
+    });
+    Ok(salvage)
+}
+
+fn read_segment(fs: &dyn FileSystem, dir: &Path, gen: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    fs.open_read(&log_path(dir, gen))?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Part of a segment found by `scan_records`.
+enum Span<'a> {
+    /// An intact record at an offset and its payload.
+    Record(usize, &'a [u8]),
+    /// Bytes up to the next intact record or the end of the segment.
+    Damaged(Range<usize>),
+}
+
+/// Walks `data` once from the start, passing its records and the damaged
+/// ranges between them to `f` in order.
+fn scan_records<'a>(data: &'a [u8], mut f: impl FnMut(Span<'a>)) {
+    let mut pos = 0;
+    let mut damaged = None;
+    while pos < data.len() {
+        match record_at(data, pos) {
+            Some(payload) => {
+                if let Some(start) = damaged.take() {
+                    f(Span::Damaged(start..pos));
+                }
+                f(Span::Record(pos, payload));
+                pos += HEADER_LEN as usize + payload.len();
+            }
+            None => {
+                damaged.get_or_insert(pos);
+                pos += 1;
+            }
+        }
+    }
+    if let Some(start) = damaged {
+        f(Span::Damaged(start..data.len()));
+    }
+}
+
+/// Returns the payload of the record at `pos` if it is intact.
+fn record_at(data: &[u8], pos: usize) -> Option<&[u8]> {
+    let mut header = data.get(pos..pos + HEADER_LEN as usize)?;
+    let len = header.get_u32();
+    let crc = header.get_u32();
+    let start = pos + HEADER_LEN as usize;
+    let payload = data.get(start..start + len as usize)?;
+    (checksum(len, payload) == crc).then_some(payload)
+}
+
+/// Describes why the record at `pos` is not intact.
+fn damage_at(data: &[u8], pos: usize) -> &'static str {
+    let mut header = match data.get(pos..pos + HEADER_LEN as usize) {
+        Some(header) => header,
+        None => return "truncated header",
+    };
+    if data.len() - pos - (HEADER_LEN as usize) < header.get_u32() as usize {
+        "truncated payload"
+    } else {
+        "checksum mismatch"
+    }
+}
+
+/// Writes a complete segment `gen` holding `payloads`. The segment only
+/// appears under its name once all records are durable.
+pub fn write_segment<'a>(
+    fs: &dyn FileSystem,
+    dir: &Path,
+    gen: u64,
+    payloads: impl IntoIterator<Item = &'a [u8]>,
+) -> Result<()> {
+    let path = log_path(dir, gen);
+    let tmp = path.with_extension("log.tmp");
+    let mut file = fs.open_write(&tmp)?;
+    file.set_len(0)?;
+    let mut writer = BufWriteWithPos::new(file)?;
+    for payload in payloads {
+        write_record(&mut writer, payload)?;
+    }
+    writer.writer.get_mut().sync_data()?;
+    fs.rename(&tmp, &path)?;
+    fs.sync_dir(dir)?;
+    Ok(())
+}
+
+/// Truncates a record torn by a crash from the end of a segment.
+///
+/// Only the last record may be damaged; a bad record followed by more
+/// data is reported as corruption. The segment is synced either way so
+/// that it cannot lose records the next segment builds on.
+fn repair_tail(fs: &dyn FileSystem, dir: &Path, gen: u64) -> Result<()> {
+    let check = check_segment(fs, dir, gen, |_, _| {})?;
+    let path = log_path(dir, gen);
+    let mut file = fs.open_write(&path)?;
+    if let Some(what) = check.error {
+        if !check.torn {
+            return Err(KvsError::Corruption(what));
+        }
+        log::warn!("{:?}: truncating torn record at {}", path, check.valid_len);
+        file.set_len(check.valid_len)?;
+    }
+    file.sync_data()?;
+    Ok(())
+}
+
+fn corrupted(gen: u64, pos: u64, what: &str) -> KvsError {
+    KvsError::Corruption(format!("segment {} at {}: {}", gen, pos, what))
+}
+
+fn new_log_file(fs: &dyn FileSystem, dir: &Path, gen: u64) -> Result<FileWriter> {
+    let writer = BufWriteWithPos::new(fs.open_write(&log_path(dir, gen))?)?;
+    fs.sync_dir(dir)?;
+    Ok(writer)
+}
+
+/// Returns the generations of all segments in `path`, sorted.
+pub fn sorted_gen_list(fs: &dyn FileSystem, path: &Path) -> Result<Vec<u64>> {
+    let mut gen_list: Vec<u64> =
+        // 取目录中的文件
+        fs.list_files(path)?
+        .into_iter()
+        // filter 筛选元素返回迭代器
+        // extension 提取文件扩展名
+        .filter(|path| path.extension() == Some("log".as_ref()))
+        .flat_map(|path| {
+            path.file_name()
+                .and_then(OsStr::to_str)
+                .map(|s| s.trim_end_matches(".log"))
+                .map(str::parse::<u64>)
+        })
+        .flatten()
+        .collect();
+    gen_list.sort_unstable();
+    Ok(gen_list)
+}
+
+pub fn log_path(dir: &Path, gen: u64) -> PathBuf {
+    dir.join(format!("{}.log", gen))
+}
+
+struct BufReaderWithPos<R: Read + Seek> {
+    reader : BufReader<R>,
+    pos : u64,
+}
+
+impl<R: Read + Seek> BufReaderWithPos<R>{
+    fn new(mut inner : R) -> Result<Self> {
+        let pos = inner.stream_position()?;
+        Ok(BufReaderWithPos {
+            reader : BufReader::new(inner),
+            pos
+        })
+    }
+}
+
+impl<R:Read + Seek> Read for BufReaderWithPos<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R:Read + Seek> Seek for BufReaderWithPos<R> {
+    fn seek(&mut self, pos : SeekFrom) -> io::Result<u64> {
+        self.pos = self.reader.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+struct BufWriteWithPos<W : Write + Seek> {
+    writer : BufWriter<W>,
+    pos : u64,
+}
+
+impl<W: Write + Seek> BufWriteWithPos<W> {
+    fn new(mut inner : W) -> Result<Self> {
+        let pos = inner.seek(SeekFrom::End(0))?;
+        Ok(BufWriteWithPos {
+            writer : BufWriter::new(inner),
+            pos
+        })
+    }
+}
+
+impl<W:Write + Seek> Write for BufWriteWithPos<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.writer.write(buf)?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W:Write + Seek> Seek for BufWriteWithPos<W> {
+    fn seek(&mut self, pos : SeekFrom) -> io::Result<u64> {
+        self.pos = self.writer.seek(pos)?;
+        Ok(self.pos)
+    }
+}
@@ -1,5 +1,11 @@
+// `failure`'s derive expands to impls nested in a const block
+#![allow(non_local_definitions)]
+
 use failure::Fail;
 use std::io;
+use std::string::FromUtf8Error;
+
+use crate::common::ErrorCode;
 
 #[derive(Fail, Debug)]
 pub enum KvsError {
@@ -9,17 +15,54 @@ pub enum KvsError {
     #[fail(display = "{}", _0)]
     Serde(#[cause] serde_json::Error),
 
+    #[fail(display = "sled error: {}", _0)]
+    Sled(#[cause] sled::Error),
+
+    #[fail(display = "UTF-8 error: {}", _0)]
+    Utf8(#[cause] FromUtf8Error),
+
+    #[fail(display = "CSV error: {}", _0)]
+    Csv(#[cause] csv::Error),
+
     #[fail(display = "key not found")]
     KeyNotFound,
 
-    #[fail(display = "Unexpcetd command type")]
+    #[fail(display = "unexpected command type")]
     UnexpectedCommandType,
 
-    #[fail(display = "InvalidRequest")]
+    #[fail(display = "invalid request")]
     InvalidRequest,
 
-    #[fail(display = "InvalidReply")]
-    InvalidReply
+    #[fail(display = "invalid reply")]
+    InvalidReply,
+
+    #[fail(display = "corrupted data: {}", _0)]
+    Corruption(String),
+
+    #[fail(display = "namespace not found: {}", _0)]
+    NamespaceNotFound(String),
+
+    #[fail(display = "namespace already exists: {}", _0)]
+    NamespaceExists(String),
+
+    #[fail(display = "invalid namespace: {:?}", _0)]
+    InvalidNamespace(String),
+
+    #[fail(display = "not supported by this engine: {}", _0)]
+    Unsupported(String),
+
+    #[fail(display = "invalid backup: {}", _0)]
+    Backup(String),
+
+    #[fail(display = "invalid record: {}", _0)]
+    InvalidRecord(String),
+
+    #[fail(display = "incompatible protocol: {}", _0)]
+    Incompatible(String),
+
+    /// An error the server replied with, other than a missing key.
+    #[fail(display = "{}", _1)]
+    Server(ErrorCode, String),
 }
 
 impl From<io::Error> for KvsError {
@@ -28,4 +71,28 @@ impl From<io::Error> for KvsError {
     }
 }
 
+impl From<serde_json::Error> for KvsError {
+    fn from(err: serde_json::Error) -> Self {
+        KvsError::Serde(err)
+    }
+}
+
+impl From<sled::Error> for KvsError {
+    fn from(err: sled::Error) -> Self {
+        KvsError::Sled(err)
+    }
+}
+
+impl From<csv::Error> for KvsError {
+    fn from(err: csv::Error) -> Self {
+        KvsError::Csv(err)
+    }
+}
+
+impl From<FromUtf8Error> for KvsError {
+    fn from(err: FromUtf8Error) -> Self {
+        KvsError::Utf8(err)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, KvsError>;
@@ -0,0 +1,193 @@
+use std::{
+    collections::HashSet,
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::engines::KvsEngine;
+use crate::error::{KvsError, Result};
+
+const BASE64 : &str = "base64";
+
+/// Formats written by `ExportWriter` and read by `ImportReader`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    JsonLines,
+    /// A header row followed by one row per pair.
+    Csv,
+}
+
+/// One exported pair.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExportRecord {
+    pub ns : String,
+    pub key : String,
+    pub value : String,
+    /// `base64` if `value` is encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding : Option<String>,
+}
+
+/// Writes pairs in one of the `ExportFormat`s.
+pub struct ExportWriter<W : Write> {
+    sink : Sink<W>,
+    base64 : bool,
+}
+
+enum Sink<W : Write> {
+    JsonLines(W),
+    Csv(Box<csv::Writer<W>>),
+}
+
+impl<W : Write> ExportWriter<W> {
+    /// With `base64` set every value is written base64-encoded, which keeps
+    /// line breaks and control characters out of the output. Values are
+    /// UTF-8 strings like everywhere else in kvs, so the encoded bytes are
+    /// always UTF-8 as well.
+    pub fn new(out : W, format : ExportFormat, base64 : bool) -> Self {
+        let sink = match format {
+            ExportFormat::JsonLines => Sink::JsonLines(out),
+            ExportFormat::Csv => Sink::Csv(Box::new(csv::Writer::from_writer(out))),
+        };
+        ExportWriter { sink, base64 }
+    }
+
+    pub fn write(&mut self, ns : &str, key : &str, value : &str) -> Result<()> {
+        let (value, encoding) = match self.base64 {
+            true => (STANDARD.encode(value), Some(BASE64.to_owned())),
+            false => (value.to_owned(), None),
+        };
+        let record = ExportRecord {
+            ns : ns.to_owned(),
+            key : key.to_owned(),
+            value,
+            encoding,
+        };
+        match &mut self.sink {
+            Sink::JsonLines(out) => {
+                serde_json::to_writer(&mut *out, &record)?;
+                out.write_all(b"\n")?;
+            }
+            Sink::Csv(out) => out.serialize(&record)?,
+        }
+        Ok(())
+    }
+
+    /// Flushes the written records and returns the underlying writer.
+    pub fn finish(self) -> Result<W> {
+        match self.sink {
+            Sink::JsonLines(mut out) => {
+                out.flush()?;
+                Ok(out)
+            }
+            Sink::Csv(out) => out.into_inner().map_err(|e| e.into_error().into()),
+        }
+    }
+}
+
+/// Reads the records written by `ExportWriter`, decoding base64 values.
+/// A base64 value must decode to UTF-8, as the engines only store strings.
+pub struct ImportReader<R : Read> {
+    source : Source<R>,
+    record : u64,
+}
+
+enum Source<R : Read> {
+    JsonLines(io::Lines<BufReader<R>>),
+    Csv(csv::DeserializeRecordsIntoIter<R, ExportRecord>),
+}
+
+impl<R : Read> ImportReader<R> {
+    pub fn new(input : R, format : ExportFormat) -> Self {
+        let source = match format {
+            ExportFormat::JsonLines => Source::JsonLines(BufReader::new(input).lines()),
+            ExportFormat::Csv => Source::Csv(csv::Reader::from_reader(input).into_deserialize()),
+        };
+        ImportReader { source, record : 0 }
+    }
+
+    fn next_record(&mut self) -> Option<Result<ExportRecord>> {
+        self.record += 1;
+        match &mut self.source {
+            Source::JsonLines(lines) => loop {
+                match lines.next()? {
+                    Ok(line) if line.trim().is_empty() => continue,
+                    Ok(line) => return Some(serde_json::from_str(&line).map_err(KvsError::from)),
+                    Err(e) => return Some(Err(e.into())),
+                }
+            },
+            Source::Csv(records) => Some(records.next()?.map_err(KvsError::from)),
+        }
+    }
+}
+
+impl<R : Read> Iterator for ImportReader<R> {
+    type Item = Result<ExportRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.next_record()?.and_then(decode);
+        let number = self.record;
+        Some(record.map_err(|e| match e {
+            KvsError::Io(e) => KvsError::Io(e),
+            e => KvsError::InvalidRecord(format!("record {}: {}", number, e)),
+        }))
+    }
+}
+
+fn decode(mut record : ExportRecord) -> Result<ExportRecord> {
+    match record.encoding.take().as_deref() {
+        None => Ok(record),
+        Some(BASE64) => {
+            let value = STANDARD
+                .decode(&record.value)
+                .map_err(|e| KvsError::InvalidRecord(e.to_string()))?;
+            record.value = String::from_utf8(value)
+                .map_err(|_| KvsError::InvalidRecord("base64 value does not decode to UTF-8".to_owned()))?;
+            Ok(record)
+        }
+        Some(other) => Err(KvsError::InvalidRecord(format!("unknown encoding {:?}", other))),
+    }
+}
+
+/// Writes the pairs whose key starts with `prefix`, from namespace `ns` or
+/// from every namespace if `None`. Returns the number of pairs written.
+pub fn export<W : Write>(
+    engine : &impl KvsEngine,
+    ns : Option<&str>,
+    prefix : &str,
+    out : &mut ExportWriter<W>,
+) -> Result<u64> {
+    let namespaces = match ns {
+        Some(ns) => vec![ns.to_owned()],
+        None => engine.list_namespaces()?,
+    };
+    let mut count = 0;
+    for ns in &namespaces {
+        for pair in engine.scan_in(ns, prefix)? {
+            let (key, value) = pair?;
+            out.write(ns, &key, &value)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Sets every pair read from `input`, creating missing namespaces. Returns
+/// the number of pairs imported.
+pub fn import<R : Read>(engine : &mut impl KvsEngine, input : ImportReader<R>) -> Result<u64> {
+    let mut namespaces : HashSet<String> = engine.list_namespaces()?.into_iter().collect();
+    let mut count = 0;
+    for record in input {
+        let record = record?;
+        if !namespaces.contains(&record.ns) {
+            engine.create_namespace(&record.ns)?;
+            namespaces.insert(record.ns.clone());
+        }
+        engine.set_in(&record.ns, record.key, record.value)?;
+        count += 1;
+    }
+    Ok(count)
+}
@@ -1,7 +1,20 @@
 mod error;
+mod client;
 mod common;
 mod engines;
+mod export;
+mod migrate;
+mod thread_pool;
 
-use crate::error::{KvsError, Result};
-pub use crate::engines::{KvsEngine, KvStore, SledKvsEngine};
+pub use crate::error::{KvsError, Result};
+pub use crate::client::{AsyncKvsClient, ClientOptions, Idempotency, KvsClient, KvsClientPool, PoolOptions, RemoteEngine};
+pub use crate::engines::{
+    check_dir, detect_engine, read_engine_marker, repair_dir, restore_dir, write_engine_marker, BackupJob, BackupManifest, CheckReport, DamagedRange, FileSystem, FsFile, KvStore, KvsEngine,
+    DEFAULT_NAMESPACE, LogEnd, LsmEngine, LsmOptions, MemoryEngine, OsFileSystem, QUARANTINE_DIR, RecordPos, RepairReport,
+    Replay, RestorePoint, RestoreReport, Scan, SegmentCopy, SegmentRepair, SegmentReport, SegmentStatus, SledKvsEngine,
+    SyncPolicy, Wal, BACKUP_MANIFEST, ENGINE_MARKER,
+};
+pub use crate::export::{export, import, ExportFormat, ExportRecord, ExportWriter, ImportReader};
+pub use crate::migrate::{migrate, verify, Checkpoint, Difference, DifferenceKind, VerifyReport};
+pub use crate::thread_pool::{NaiveThreadPool, SharedQueueThreadPool, ThreadPool, WorkStealingThreadPool};
 pub use crate::common::*;
\ No newline at end of file
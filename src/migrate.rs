@@ -0,0 +1,209 @@
+use std::{
+    cmp::Ordering,
+    fs, io,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::engines::KvsEngine;
+use crate::error::{KvsError, Result};
+
+/// Differences kept in a `VerifyReport`; the rest are only counted.
+const MAX_DIFFERENCES : usize = 100;
+
+/// Progress of `migrate`, saved after every batch so an interrupted run can
+/// resume. Namespaces and keys are copied in sorted order.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Namespace being copied; the ones sorting before it are complete.
+    pub ns : Option<String>,
+    /// Last key of `ns` copied.
+    pub key : Option<String>,
+    pub copied : u64,
+    pub done : bool,
+}
+
+impl Checkpoint {
+    /// Reads the checkpoint at `path`, or starts afresh if there is none.
+    pub fn load(path : &Path) -> Result<Checkpoint> {
+        match fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Checkpoint::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces the checkpoint at `path` atomically.
+    pub fn save(&self, path : &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::File::open(&tmp)?.sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Copies every pair of `from` into `to`, creating missing namespaces, and
+/// returns the final checkpoint.
+///
+/// `to` is synced before each save of the checkpoint at `checkpoint`, so a
+/// resumed run only repeats writes of the batch that was interrupted, and
+/// repeating a write changes nothing.
+pub fn migrate<F, T>(from : &F, to : &mut T, checkpoint : &Path, batch : usize) -> Result<Checkpoint>
+where
+    F : KvsEngine + ?Sized,
+    T : KvsEngine + ?Sized,
+{
+    let mut progress = Checkpoint::load(checkpoint)?;
+    if progress.done {
+        return Ok(progress);
+    }
+
+    let existing = to.list_namespaces()?;
+    for ns in from.list_namespaces()? {
+        if progress.ns.as_ref().is_some_and(|current| ns < *current) {
+            continue;
+        }
+        if progress.ns.as_ref() != Some(&ns) {
+            progress.ns = Some(ns.clone());
+            progress.key = None;
+        }
+        if !existing.contains(&ns) {
+            match to.create_namespace(&ns) {
+                Ok(()) | Err(KvsError::NamespaceExists(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut pending = 0;
+        for pair in from.scan_in(&ns, "")? {
+            let (key, value) = pair?;
+            if progress.key.as_ref().is_some_and(|last| key <= *last) {
+                continue;
+            }
+            to.set_in(&ns, key.clone(), value)?;
+            progress.key = Some(key);
+            progress.copied += 1;
+            pending += 1;
+            if pending == batch {
+                to.sync()?;
+                progress.save(checkpoint)?;
+                pending = 0;
+            }
+        }
+    }
+
+    to.sync()?;
+    progress.done = true;
+    progress.save(checkpoint)?;
+    Ok(progress)
+}
+
+/// Outcome of `verify`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    /// `true` if both sides hold the same namespaces and pairs.
+    pub ok : bool,
+    /// Pairs found on both sides with equal values.
+    pub matched : u64,
+    /// Pairs and namespaces only in the source.
+    pub missing : u64,
+    /// Pairs and namespaces only in the target.
+    pub extra : u64,
+    /// Keys whose values differ.
+    pub mismatched : u64,
+    /// The first differences found.
+    pub differences : Vec<Difference>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Difference {
+    pub ns : String,
+    /// `None` if the whole namespace exists on one side only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key : Option<String>,
+    pub kind : DifferenceKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DifferenceKind {
+    Missing,
+    Extra,
+    Mismatched,
+}
+
+impl VerifyReport {
+    fn record(&mut self, ns : &str, key : Option<String>, kind : DifferenceKind) {
+        match kind {
+            DifferenceKind::Missing => self.missing += 1,
+            DifferenceKind::Extra => self.extra += 1,
+            DifferenceKind::Mismatched => self.mismatched += 1,
+        }
+        if self.differences.len() < MAX_DIFFERENCES {
+            self.differences.push(Difference { ns : ns.to_owned(), key, kind });
+        }
+    }
+}
+
+/// Compares every namespace and pair of `from` and `to`, walking both sides
+/// in key order.
+pub fn verify<F, T>(from : &F, to : &T) -> Result<VerifyReport>
+where
+    F : KvsEngine + ?Sized,
+    T : KvsEngine + ?Sized,
+{
+    let mut report = VerifyReport::default();
+    let (sources, targets) = (from.list_namespaces()?, to.list_namespaces()?);
+    for ns in &sources {
+        if !targets.contains(ns) {
+            report.record(ns, None, DifferenceKind::Missing);
+            report.missing += count(from, ns)?;
+            continue;
+        }
+        let mut source = from.scan_in(ns, "")?;
+        let mut target = to.scan_in(ns, "")?;
+        let (mut left, mut right) = (source.next().transpose()?, target.next().transpose()?);
+        loop {
+            let order = match (&left, &right) {
+                (None, None) => break,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((a, _)), Some((b, _))) => a.cmp(b),
+            };
+            match order {
+                Ordering::Less => {
+                    let (key, _) = left.take().expect("compared above");
+                    report.record(ns, Some(key), DifferenceKind::Missing);
+                    left = source.next().transpose()?;
+                }
+                Ordering::Greater => {
+                    let (key, _) = right.take().expect("compared above");
+                    report.record(ns, Some(key), DifferenceKind::Extra);
+                    right = target.next().transpose()?;
+                }
+                Ordering::Equal => {
+                    let ((key, a), (_, b)) = (left.take().expect("compared above"), right.take().expect("compared above"));
+                    if a == b {
+                        report.matched += 1;
+                    } else {
+                        report.record(ns, Some(key), DifferenceKind::Mismatched);
+                    }
+                    left = source.next().transpose()?;
+                    right = target.next().transpose()?;
+                }
+            }
+        }
+    }
+    for ns in targets.iter().filter(|ns| !sources.contains(ns)) {
+        report.record(ns, None, DifferenceKind::Extra);
+        report.extra += count(to, ns)?;
+    }
+    report.ok = report.missing == 0 && report.extra == 0 && report.mismatched == 0;
+    Ok(report)
+}
+
+fn count<E : KvsEngine + ?Sized>(engine : &E, ns : &str) -> Result<u64> {
+    engine.scan_in(ns, "")?.try_fold(0, |count, pair| pair.map(|_| count + 1))
+}
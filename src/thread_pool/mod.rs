@@ -0,0 +1,33 @@
+use std::io;
+
+use crate::{KvsError, Result};
+
+/// Runs jobs on a set of threads. The server hands each connection to one.
+pub trait ThreadPool {
+    /// Creates a pool running jobs on `threads` threads.
+    fn new(threads : u32) -> Result<Self>
+    where
+        Self : Sized;
+
+    /// Runs `job` on the pool. A job that panics does not take the pool
+    /// down with it.
+    fn spawn<F>(&self, job : F)
+    where
+        F : FnOnce() + Send + 'static;
+}
+
+/// Rejects pools without threads, which would never run a job.
+fn check_threads(threads : u32) -> Result<()> {
+    if threads == 0 {
+        return Err(KvsError::Io(io::Error::new(io::ErrorKind::InvalidInput, "a thread pool needs a thread")));
+    }
+    Ok(())
+}
+
+pub mod naive;
+pub mod shared_queue;
+pub mod work_stealing;
+
+pub use naive::NaiveThreadPool;
+pub use shared_queue::SharedQueueThreadPool;
+pub use work_stealing::WorkStealingThreadPool;
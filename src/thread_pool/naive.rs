@@ -0,0 +1,22 @@
+use std::thread;
+
+use super::{check_threads, ThreadPool};
+use crate::Result;
+
+/// Spawns a new thread for every job, whatever the number of threads asked
+/// for. The baseline the other pools are measured against.
+pub struct NaiveThreadPool;
+
+impl ThreadPool for NaiveThreadPool {
+    fn new(threads : u32) -> Result<Self> {
+        check_threads(threads)?;
+        Ok(NaiveThreadPool)
+    }
+
+    fn spawn<F>(&self, job : F)
+    where
+        F : FnOnce() + Send + 'static,
+    {
+        thread::spawn(job);
+    }
+}
@@ -0,0 +1,47 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    thread,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+
+use super::{check_threads, ThreadPool};
+use crate::Result;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed set of threads taking jobs from one shared queue. Dropping the
+/// pool lets the threads finish the queued jobs and exit.
+pub struct SharedQueueThreadPool {
+    jobs : Sender<Job>,
+}
+
+impl ThreadPool for SharedQueueThreadPool {
+    fn new(threads : u32) -> Result<Self> {
+        check_threads(threads)?;
+        let (jobs, queue) = crossbeam_channel::unbounded::<Job>();
+        for i in 0..threads {
+            let queue = queue.clone();
+            thread::Builder::new()
+                .name(format!("kvs-worker-{}", i))
+                .spawn(move || run_jobs(queue))?;
+        }
+        Ok(SharedQueueThreadPool { jobs })
+    }
+
+    fn spawn<F>(&self, job : F)
+    where
+        F : FnOnce() + Send + 'static,
+    {
+        self.jobs
+            .send(Box::new(job))
+            .expect("the workers only exit once the pool is dropped");
+    }
+}
+
+fn run_jobs(queue : Receiver<Job>) {
+    for job in queue {
+        // the panic is reported by the hook; the thread goes on with the next job
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+    }
+}
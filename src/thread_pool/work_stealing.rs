@@ -0,0 +1,31 @@
+use std::io;
+
+use super::{check_threads, ThreadPool};
+use crate::Result;
+
+/// Gives each thread its own queue, from which idle threads steal jobs.
+/// Built on rayon.
+pub struct WorkStealingThreadPool {
+    pool : rayon::ThreadPool,
+}
+
+impl ThreadPool for WorkStealingThreadPool {
+    fn new(threads : u32) -> Result<Self> {
+        check_threads(threads)?;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .thread_name(|i| format!("kvs-worker-{}", i))
+            // keep the thread alive when a job panics
+            .panic_handler(|_| {})
+            .build()
+            .map_err(io::Error::other)?;
+        Ok(WorkStealingThreadPool { pool })
+    }
+
+    fn spawn<F>(&self, job : F)
+    where
+        F : FnOnce() + Send + 'static,
+    {
+        self.pool.spawn(job);
+    }
+}
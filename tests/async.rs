@@ -0,0 +1,55 @@
+use assert_cmd::prelude::*;
+use common::{free_addr, server_command, start_server};
+use kvs::{AsyncKvsClient, KvsError};
+use predicates::str::contains;
+use std::time::Duration;
+use tokio::time::timeout;
+
+mod common;
+
+// The async server answers the async client, and idle connections do not
+// hold its only worker thread
+#[tokio::test]
+async fn async_client_and_server() {
+    let (server, addr) = start_server(&["--engine", "memory", "--threads", "1", "--async"]);
+
+    let mut idle = Vec::new();
+    for _ in 0..20 {
+        idle.push(AsyncKvsClient::connect(&addr).await.unwrap());
+    }
+
+    let mut client = timeout(Duration::from_secs(5), AsyncKvsClient::connect(&addr))
+        .await
+        .expect("idle connections stalled the server")
+        .unwrap();
+    client.set("key1".to_owned(), "value1".to_owned()).await.unwrap();
+    assert_eq!(client.get("key1".to_owned()).await.unwrap(), Some("value1".to_owned()));
+    client.set("key1".to_owned(), "value2".to_owned()).await.unwrap();
+    assert_eq!(idle[0].get("key1".to_owned()).await.unwrap(), Some("value2".to_owned()));
+
+    client.remove("key1".to_owned()).await.unwrap();
+    assert_eq!(client.get("key1".to_owned()).await.unwrap(), None);
+    assert!(matches!(client.remove("key1".to_owned()).await, Err(KvsError::KeyNotFound)));
+
+    // the blocking client speaks the same protocol
+    let cli_addr = addr.clone();
+    tokio::task::spawn_blocking(move || {
+        assert_cmd::Command::cargo_bin("kvs-client")
+            .unwrap()
+            .args(["set", "key2", "value3", "--ipaddr", &cli_addr])
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success();
+    })
+    .await
+    .unwrap();
+    assert_eq!(client.get("key2".to_owned()).await.unwrap(), Some("value3".to_owned()));
+
+    drop(idle);
+    drop(server);
+
+    server_command(&free_addr(), &["--engine", "memory", "--async", "--pool", "naive"])
+        .assert()
+        .code(2)
+        .stderr(contains("cannot be used with"));
+}
@@ -0,0 +1,306 @@
+use assert_cmd::prelude::*;
+use kvs::{
+    restore_dir, BackupManifest, KvStore, KvsEngine, KvsError, MemoryEngine, RestorePoint, Result, BACKUP_MANIFEST,
+};
+use predicates::str::contains;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+// The backup holds exactly the state at the time it was taken, across
+// sealed and active generations
+#[test]
+fn backup_is_point_in_time() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let backup_dir = temp_dir.path().join("backup");
+    let data_dir = temp_dir.path().join("data");
+
+    for gen in 0..3 {
+        let mut store = KvStore::open(&data_dir)?;
+        for i in gen * 10..(gen + 1) * 10 {
+            store.set(format!("key{}", i), format!("value{}", i))?;
+        }
+    }
+    let mut store = KvStore::open(&data_dir)?;
+    store.remove("key0".to_owned())?;
+    store.backup_to(&backup_dir)?;
+
+    store.set("key1".to_owned(), "changed".to_owned())?;
+    store.set("key30".to_owned(), "value30".to_owned())?;
+
+    let manifest: BackupManifest =
+        serde_json::from_slice(&fs::read(backup_dir.join(BACKUP_MANIFEST))?)?;
+    assert!(manifest.segments.len() >= 3);
+    for segment in &manifest.segments {
+        let path = backup_dir.join(format!("{}.log", segment.gen));
+        assert_eq!(fs::metadata(path)?.len(), segment.len);
+    }
+
+    let backup = KvStore::open(&backup_dir)?;
+    assert_eq!(backup.get("key0".to_owned())?, None);
+    assert_eq!(backup.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(backup.get("key29".to_owned())?, Some("value29".to_owned()));
+    assert_eq!(backup.get("key30".to_owned())?, None);
+
+    // the original keeps working, and compacting it leaves the backup intact
+    drop(backup);
+    for i in 0..2000 {
+        store.set("key1".to_owned(), format!("changed{}", i))?;
+    }
+    drop(store);
+    let backup = KvStore::open(&backup_dir)?;
+    assert_eq!(backup.get("key1".to_owned())?, Some("value1".to_owned()));
+
+    Ok(())
+}
+
+// The store keeps serving writes, and compacting, while a started backup
+// copies the log
+#[test]
+fn backup_copies_after_store_is_released() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let backup_dir = temp_dir.path().join("backup");
+    let mut store = KvStore::open(temp_dir.path().join("data"))?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+
+    let job = store.start_backup(&backup_dir, None)?;
+    for i in 0..2000 {
+        store.set("key1".to_owned(), format!("changed{}", i))?;
+    }
+    store.compact()?;
+    job()?;
+
+    let backup = KvStore::open(&backup_dir)?;
+    assert_eq!(backup.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(store.get("key1".to_owned())?, Some("changed1999".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn backup_into_non_empty_dir_fails() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let backup_dir = temp_dir.path().join("backup");
+    let mut store = KvStore::open(temp_dir.path().join("data"))?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+
+    fs::create_dir(&backup_dir)?;
+    fs::write(backup_dir.join("other"), "keep me")?;
+    assert!(store.backup_to(&backup_dir).is_err());
+    assert_eq!(fs::read_to_string(backup_dir.join("other"))?, "keep me");
+
+    Ok(())
+}
+
+// Full backup, then an incremental one in the same run and one after a restart
+fn backup_chain(dir: &Path) -> Result<Vec<PathBuf>> {
+    let backups: Vec<PathBuf> = (0..3).map(|i| dir.join(format!("backup{}", i))).collect();
+    let mut store = KvStore::open(dir.join("data"))?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.backup_to(&backups[0])?;
+
+    store.set("key2".to_owned(), "value2".to_owned())?;
+    store.set("key1".to_owned(), "changed".to_owned())?;
+    store.backup_incremental_to(&backups[1], &backups[0])?;
+    drop(store);
+
+    let mut store = KvStore::open(dir.join("data"))?;
+    store.remove("key2".to_owned())?;
+    store.set("key3".to_owned(), "value3".to_owned())?;
+    store.backup_incremental_to(&backups[2], &backups[1])?;
+    Ok(backups)
+}
+
+fn restored(dir: &Path, backups: &[PathBuf], point: RestorePoint) -> Result<Vec<Option<String>>> {
+    restore_dir(dir, backups, point)?;
+    let store = KvStore::open(dir)?;
+    (1..4).map(|i| store.get(format!("key{}", i))).collect()
+}
+
+#[test]
+fn incremental_backup_restore() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let backups = backup_chain(temp_dir.path())?;
+
+    let manifest = BackupManifest::read(&backups[2])?;
+    assert_eq!((manifest.start_seq, manifest.end_seq), (2, 4));
+    // only the records written after the parent are shipped
+    let shipped: u64 = manifest.segments.iter().map(|segment| segment.len).sum();
+    let total: u64 = fs::read_dir(temp_dir.path().join("data"))?
+        .map(|entry| entry.unwrap().metadata().unwrap().len())
+        .sum();
+    assert!(shipped * 2 < total);
+
+    let some = |value: &str| Some(value.to_owned());
+    assert_eq!(
+        restored(&temp_dir.path().join("latest"), &backups, RestorePoint::Latest)?,
+        vec![some("changed"), None, some("value3")]
+    );
+    assert_eq!(
+        restored(&temp_dir.path().join("seq1"), &backups, RestorePoint::Seq(1))?,
+        vec![some("value1"), some("value2"), None]
+    );
+    assert_eq!(
+        restored(&temp_dir.path().join("seq3"), &backups, RestorePoint::Seq(3))?,
+        vec![some("changed"), None, None]
+    );
+    assert_eq!(
+        restored(&temp_dir.path().join("full"), &backups[..1], RestorePoint::Latest)?,
+        vec![some("value1"), None, None]
+    );
+
+    // backups taken later than the restore time are left out
+    let mut manifest = BackupManifest::read(&backups[1])?;
+    manifest.created_at += 100;
+    fs::write(backups[1].join(BACKUP_MANIFEST), serde_json::to_vec(&manifest)?)?;
+    assert_eq!(
+        restored(&temp_dir.path().join("time"), &backups, RestorePoint::Time(manifest.created_at - 1))?,
+        vec![some("value1"), None, None]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn restore_rejects_broken_chain() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let backups = backup_chain(temp_dir.path())?;
+    let target = temp_dir.path().join("restored");
+
+    let gap = [backups[0].clone(), backups[2].clone()];
+    assert!(matches!(restore_dir(&target, &gap, RestorePoint::Latest), Err(KvsError::Backup(_))));
+    assert!(matches!(restore_dir(&target, &backups[1..], RestorePoint::Latest), Err(KvsError::Backup(_))));
+    assert!(matches!(restore_dir(&target, &backups, RestorePoint::Seq(5)), Err(KvsError::Backup(_))));
+    assert!(matches!(restore_dir(&target, &backups, RestorePoint::Time(0)), Err(KvsError::Backup(_))));
+    assert!(!target.exists() || fs::read_dir(&target)?.next().is_none());
+
+    Ok(())
+}
+
+// A compaction rewrites the log, so an incremental backup would miss removals
+#[test]
+fn incremental_backup_after_compaction_fails() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let full = temp_dir.path().join("full");
+    let mut store = KvStore::open(temp_dir.path().join("data"))?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.backup_to(&full)?;
+    store.remove("key1".to_owned())?;
+    store.compact()?;
+
+    let result = store.backup_incremental_to(&temp_dir.path().join("incremental"), &full);
+    assert!(matches!(result, Err(KvsError::Backup(_))));
+
+    Ok(())
+}
+
+#[test]
+fn backup_unsupported_engine() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut engine = MemoryEngine::new();
+    assert!(matches!(
+        engine.backup_to(temp_dir.path()),
+        Err(KvsError::Unsupported(_))
+    ));
+    assert!(matches!(
+        engine.backup_incremental_to(&temp_dir.path().join("next"), temp_dir.path()),
+        Err(KvsError::Unsupported(_))
+    ));
+}
+
+#[test]
+fn cli_backup() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let data_dir = temp_dir.path().join("data");
+    let backup_root = temp_dir.path().join("backups");
+    let backup_dir = backup_root.join("backup");
+    let incremental_dir = backup_root.join("incremental");
+    let restore_dir = temp_dir.path().join("restored");
+
+    let mut server = Command::cargo_bin("kvs-server")
+        .unwrap()
+        .args(["--engine", "kvs", "--addr", "127.0.0.1:4106", "--data-dir"])
+        .arg(&data_dir)
+        .arg("--backup-root")
+        .arg(&backup_root)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--ipaddr", "127.0.0.1:4106"])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["backup", "backup", "--ipaddr", "127.0.0.1:4106"])
+        .assert()
+        .success();
+    // a second backup into the same directory is refused
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["backup", "backup", "--ipaddr", "127.0.0.1:4106"])
+        .assert()
+        .code(17)
+        .stderr(contains("is not empty"));
+    // clients cannot name directories outside the backup root
+    for dir in [temp_dir.path().join("outside"), PathBuf::from("../outside"), PathBuf::from("backup/../../outside")] {
+        Command::cargo_bin("kvs-client")
+            .unwrap()
+            .arg("backup")
+            .arg(&dir)
+            .args(["--ipaddr", "127.0.0.1:4106"])
+            .assert()
+            .code(14)
+            .stderr(contains("inside the backup root"));
+    }
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["backup", "incremental", "--since", "/tmp", "--ipaddr", "127.0.0.1:4106"])
+        .assert()
+        .code(14);
+    assert!(!temp_dir.path().join("outside").exists());
+    assert!(!incremental_dir.exists());
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key1", "--ipaddr", "127.0.0.1:4106"])
+        .assert()
+        .success()
+        .stdout(contains("value1"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key2", "value2", "--ipaddr", "127.0.0.1:4106"])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["backup", "incremental", "--since", "backup", "--ipaddr", "127.0.0.1:4106"])
+        .assert()
+        .success()
+        .stdout(contains("Ok"));
+
+    server.kill().expect("server exited before killed");
+    server.wait().unwrap();
+
+    let backup = KvStore::open(&backup_dir)?;
+    assert_eq!(backup.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(backup.get("key2".to_owned())?, None);
+    drop(backup);
+
+    Command::cargo_bin("kvs-restore")
+        .unwrap()
+        .arg("--data-dir")
+        .arg(&restore_dir)
+        .arg(&backup_dir)
+        .arg(&incremental_dir)
+        .assert()
+        .success()
+        .stdout(contains("\"seq\": 1"));
+    let restored = KvStore::open(&restore_dir)?;
+    assert_eq!(restored.get("key2".to_owned())?, Some("value2".to_owned()));
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use assert_cmd::prelude::*;
+use kvs::{check_dir, KvStore, KvsEngine, Result, SegmentStatus};
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+use tempfile::TempDir;
+
+fn populate(dir: &TempDir) -> Result<()> {
+    let mut store = KvStore::open(dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key1".to_owned(), "value2".to_owned())?;
+    store.set("key2".to_owned(), "value3".to_owned())?;
+    store.remove("key2".to_owned())?;
+    store.create_namespace("tenant")?;
+    store.set_in("tenant", "key1".to_owned(), "value4".to_owned())?;
+    Ok(())
+}
+
+fn report(dir: &TempDir) -> serde_json::Value {
+    let output = Command::cargo_bin("kvs-check")
+        .unwrap()
+        .args(["--data-dir"])
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+// Overwritten and removed records count as dead, the rest as live
+#[test]
+fn check_counts_live_and_dead_bytes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    populate(&temp_dir)?;
+
+    let report = check_dir(temp_dir.path())?;
+    assert!(report.ok);
+    let size: u64 = report.segments.iter().map(|segment| segment.size).sum();
+    assert_eq!(report.live_bytes + report.dead_bytes, size);
+    assert_eq!(report.segments[0].records, 6);
+    assert!(report.dead_bytes > 0);
+
+    // after compaction only live records are left
+    let mut store = KvStore::open(temp_dir.path())?;
+    store.compact()?;
+    drop(store);
+    let report = check_dir(temp_dir.path())?;
+    assert!(report.ok);
+    assert_eq!(report.dead_bytes, 0);
+    assert_eq!(report.segments.iter().map(|segment| segment.records).sum::<u64>(), 3);
+
+    Command::cargo_bin("kvs-check")
+        .unwrap()
+        .args(["--data-dir"])
+        .arg(temp_dir.path())
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn check_reports_corruption() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    populate(&temp_dir)?;
+    let path = temp_dir.path().join("1.log");
+    let mut data = fs::read(&path)?;
+    data[10] ^= 0xff;
+    fs::write(&path, data)?;
+
+    Command::cargo_bin("kvs-check")
+        .unwrap()
+        .args(["--data-dir"])
+        .arg(temp_dir.path())
+        .assert()
+        .code(1);
+    let report = report(&temp_dir);
+    assert_eq!(report["ok"], false);
+    assert_eq!(report["segments"][0]["status"], "corrupt");
+    assert_eq!(report["segments"][0]["error_offset"], 0);
+
+    Ok(())
+}
+
+// A torn tail is repaired on open, so it is reported but not an error
+#[test]
+fn check_accepts_torn_tail() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    populate(&temp_dir)?;
+    let size = fs::metadata(temp_dir.path().join("1.log"))?.len();
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(temp_dir.path().join("1.log"))?;
+    file.write_all(&[0, 0, 0, 100, 1, 2])?;
+    drop(file);
+
+    let report = check_dir(temp_dir.path())?;
+    assert!(report.ok);
+    assert_eq!(report.segments[0].status, SegmentStatus::TornTail);
+    assert_eq!(report.segments[0].error_offset, Some(size));
+
+    Command::cargo_bin("kvs-check")
+        .unwrap()
+        .args(["--data-dir"])
+        .arg(temp_dir.path())
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn check_rejects_other_engines() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    fs::write(temp_dir.path().join("engine"), "sled").unwrap();
+
+    Command::cargo_bin("kvs-check")
+        .unwrap()
+        .args(["--data-dir"])
+        .arg(temp_dir.path())
+        .assert()
+        .code(2);
+}
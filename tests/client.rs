@@ -0,0 +1,92 @@
+use common::start_server;
+use kvs::{ClientOptions, ErrorCode, Features, Hello, KvsClient, KvsError, ScanCursor};
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+
+// `KvsClient` covers what `kvs-client` does, reporting failures as errors
+#[test]
+fn client_requests() {
+    let (server, addr) = start_server(&["--engine", "memory"]);
+
+    let mut client = KvsClient::connect(&*addr).unwrap();
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(client.get("key1".to_owned()).unwrap(), Some("value1".to_owned()));
+    client.remove("key1".to_owned()).unwrap();
+    assert_eq!(client.get("key1".to_owned()).unwrap(), None);
+    assert!(matches!(client.remove("key1".to_owned()), Err(KvsError::KeyNotFound)));
+
+    let err = client.set_in("ns1", "key1".to_owned(), "value1".to_owned()).unwrap_err();
+    assert!(matches!(err, KvsError::Server(ErrorCode::NamespaceNotFound, _)), "{}", err);
+    client.create_namespace("ns1").unwrap();
+    let err = client.create_namespace("ns1").unwrap_err();
+    assert!(matches!(err, KvsError::Server(ErrorCode::Conflict, _)), "{}", err);
+    assert_eq!(client.list_namespaces().unwrap(), vec!["default".to_owned(), "ns1".to_owned()]);
+
+    for i in 0..200 {
+        client.pipeline_set_in("ns1", format!("key{:03}", i), format!("value{}", i)).unwrap();
+    }
+    client.finish().unwrap();
+    let mut cursor = ScanCursor { after: None, limit: 150 };
+    let page = client.scan_in("ns1", "key", &cursor).unwrap();
+    assert_eq!(page.pairs.len(), 150);
+    assert!(page.more);
+    cursor.after = Some(page.pairs[149].0.clone());
+    let page = client.scan_in("ns1", "key", &cursor).unwrap();
+    assert_eq!(page.pairs.len(), 50);
+    assert_eq!(page.pairs[49], ("key199".to_owned(), "value199".to_owned()));
+    assert!(!page.more);
+
+    // a failed pipelined request is reported by the next call
+    client.pipeline_set_in("ns2", "key1".to_owned(), "value1".to_owned()).unwrap();
+    let err = client.get_in("ns1", "key000".to_owned()).unwrap_err();
+    assert!(matches!(err, KvsError::Server(ErrorCode::NamespaceNotFound, _)), "{}", err);
+    assert_eq!(client.get_in("ns1", "key000".to_owned()).unwrap(), Some("value0".to_owned()));
+
+    // the server caps the page length whatever the client asks for
+    for i in 0..kvs::MAX_SCAN_LIMIT - 200 + 1 {
+        client.pipeline_set_in("ns1", format!("more{:05}", i), String::new()).unwrap();
+    }
+    client.finish().unwrap();
+    let page = client.scan_in("ns1", "", &ScanCursor { after: None, limit: u32::MAX }).unwrap();
+    assert_eq!(page.pairs.len(), kvs::MAX_SCAN_LIMIT as usize);
+    assert!(page.more);
+
+    client.drop_namespace("ns1").unwrap();
+    let err = client.backup("backup", None).unwrap_err();
+    assert!(matches!(err, KvsError::Server(ErrorCode::Unsupported, _)), "{}", err);
+
+    drop(server);
+    assert!(matches!(KvsClient::connect(&*addr), Err(KvsError::Io(_))));
+}
+
+// A server that stops answering fails the request once the timeout is up,
+// and every request after it
+#[test]
+fn client_times_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        kvs::read_frame(&mut conn, KvsError::InvalidRequest).unwrap();
+        kvs::write_frame(&mut conn, &Hello::new(Features::empty()).build(), KvsError::InvalidReply).unwrap();
+        // hold the connection open without replying
+        let _ = kvs::read_frame(&mut conn, KvsError::InvalidRequest);
+        let _ = kvs::read_frame(&mut conn, KvsError::InvalidRequest);
+    });
+
+    let options = ClientOptions {
+        io_timeout: Some(Duration::from_millis(200)),
+        ..ClientOptions::default()
+    };
+    let mut client = KvsClient::connect_with_options(addr, options).unwrap();
+    let start = Instant::now();
+    assert!(matches!(client.get("key1".to_owned()), Err(KvsError::Io(_))));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(matches!(client.get("key1".to_owned()), Err(KvsError::Io(_))));
+
+    drop(client);
+    server.join().unwrap();
+}
@@ -0,0 +1,58 @@
+// Every test file includes this module but uses only some of it
+#![allow(dead_code)]
+
+use assert_cmd::prelude::*;
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest wait for a server to start accepting connections.
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Kills the server even when an assertion fails first, so it does not keep
+/// the port for later runs.
+pub struct ServerGuard(pub Child);
+
+impl Drop for ServerGuard {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Returns a localhost address with a port no socket is bound to.
+pub fn free_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// A `kvs-server` command listening on `addr`, with more `args`.
+pub fn server_command(addr: &str, args: &[&str]) -> Command {
+    let mut command = Command::cargo_bin("kvs-server").unwrap();
+    command.args(["--addr", addr]).args(args);
+    command
+}
+
+/// Starts `kvs-server` with `args` on a free port and returns it with its
+/// address once it accepts connections.
+pub fn start_server(args: &[&str]) -> (ServerGuard, String) {
+    let addr = free_addr();
+    let server = spawn_server(&mut server_command(&addr, args), &addr);
+    (server, addr)
+}
+
+/// Spawns `command`, a server listening on `addr`, and waits until it
+/// accepts connections.
+pub fn spawn_server(command: &mut Command, addr: &str) -> ServerGuard {
+    let mut server = ServerGuard(command.spawn().unwrap());
+    let deadline = Instant::now() + START_TIMEOUT;
+    while TcpStream::connect(addr).is_err() {
+        if let Some(status) = server.0.try_wait().unwrap() {
+            panic!("the server on {} exited with {}", addr, status);
+        }
+        assert!(Instant::now() < deadline, "the server on {} did not start", addr);
+        thread::sleep(Duration::from_millis(20));
+    }
+    server
+}
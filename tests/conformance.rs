@@ -0,0 +1,278 @@
+//! Behavior every `KvsEngine` must share. Each engine gets a module from
+//! `conformance!`; persistent engines additionally run the reopen tests.
+
+use kvs::{KvStore, KvsEngine, KvsError, LsmEngine, MemoryEngine, Result, SledKvsEngine, DEFAULT_NAMESPACE};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+fn get_stored_value(engine: &mut impl KvsEngine) -> Result<()> {
+    engine.set("key1".to_owned(), "value1".to_owned())?;
+    engine.set("key2".to_owned(), "value2".to_owned())?;
+
+    assert_eq!(engine.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(engine.get("key2".to_owned())?, Some("value2".to_owned()));
+    Ok(())
+}
+
+fn overwrite_value(engine: &mut impl KvsEngine) -> Result<()> {
+    engine.set("key1".to_owned(), "value1".to_owned())?;
+    engine.set("key1".to_owned(), "value2".to_owned())?;
+
+    assert_eq!(engine.get("key1".to_owned())?, Some("value2".to_owned()));
+    Ok(())
+}
+
+fn get_non_existent_value(engine: &mut impl KvsEngine) -> Result<()> {
+    engine.set("key1".to_owned(), "value1".to_owned())?;
+
+    assert_eq!(engine.get("key2".to_owned())?, None);
+    Ok(())
+}
+
+fn remove_key(engine: &mut impl KvsEngine) -> Result<()> {
+    engine.set("key1".to_owned(), "value1".to_owned())?;
+    engine.remove("key1".to_owned())?;
+
+    assert_eq!(engine.get("key1".to_owned())?, None);
+    assert!(matches!(
+        engine.remove("key1".to_owned()),
+        Err(KvsError::KeyNotFound)
+    ));
+    Ok(())
+}
+
+// Empty and non-ASCII keys and values are ordinary strings
+fn unusual_strings(engine: &mut impl KvsEngine) -> Result<()> {
+    engine.set(String::new(), "empty key".to_owned())?;
+    engine.set("empty value".to_owned(), String::new())?;
+    engine.set("键🔑".to_owned(), "值\n\0".to_owned())?;
+
+    assert_eq!(engine.get(String::new())?, Some("empty key".to_owned()));
+    assert_eq!(engine.get("empty value".to_owned())?, Some(String::new()));
+    assert_eq!(engine.get("键🔑".to_owned())?, Some("值\n\0".to_owned()));
+    Ok(())
+}
+
+fn large_values(engine: &mut impl KvsEngine) -> Result<()> {
+    let values: Vec<String> = (0..8)
+        .map(|i| char::from(b'a' + i).to_string().repeat(1 << 20))
+        .collect();
+    for (i, value) in values.iter().enumerate() {
+        engine.set(format!("key{}", i), value.clone())?;
+    }
+
+    for (i, value) in values.iter().enumerate() {
+        assert_eq!(engine.get(format!("key{}", i))?.as_ref(), Some(value));
+    }
+    Ok(())
+}
+
+// Large values make the LSM engine flush, so the scan merges tables and memtable
+fn scan_prefix(engine: &mut impl KvsEngine) -> Result<()> {
+    let large = "x".repeat(3 << 20);
+    engine.set("app1".to_owned(), large.clone())?;
+    engine.set("app2".to_owned(), "value2".to_owned())?;
+    engine.set("b".to_owned(), large.clone())?;
+    engine.set("apple".to_owned(), "value3".to_owned())?;
+    engine.set("ap".to_owned(), "value4".to_owned())?;
+    engine.remove("app2".to_owned())?;
+    engine.set("app1".to_owned(), "value1".to_owned())?;
+    engine.create_namespace("tenant")?;
+    engine.set_in("tenant", "app3".to_owned(), "tenant".to_owned())?;
+
+    let pairs = engine.scan("app")?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(
+        pairs,
+        vec![
+            ("app1".to_owned(), "value1".to_owned()),
+            ("apple".to_owned(), "value3".to_owned()),
+        ]
+    );
+    let keys = engine
+        .scan("")?
+        .map(|pair| pair.map(|(key, _)| key))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(keys, vec!["ap", "app1", "apple", "b"]);
+    // paging continues after the last key of the previous page
+    let after = |prefix: &str, after: &str| -> Result<Vec<String>> {
+        engine
+            .scan_after_in(DEFAULT_NAMESPACE, prefix, after)?
+            .map(|pair| pair.map(|(key, _)| key))
+            .collect()
+    };
+    assert_eq!(after("app", "app1")?, vec!["apple"]);
+    assert_eq!(after("app", "a")?, vec!["app1", "apple"]);
+    assert_eq!(after("", "apple")?, vec!["b"]);
+    assert!(after("app", "apple")?.is_empty());
+    let pairs = engine.scan_in("tenant", "")?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(pairs, vec![("app3".to_owned(), "tenant".to_owned())]);
+    assert!(matches!(
+        engine.scan_in("missing", ""),
+        Err(KvsError::NamespaceNotFound(_))
+    ));
+    Ok(())
+}
+
+// Engines are shared between threads behind a mutex by the server
+fn concurrent_access<E: KvsEngine + Send + 'static>(engine: E) -> Result<()> {
+    let engine = Arc::new(Mutex::new(engine));
+    let handles: Vec<_> = (0..8)
+        .map(|thread| {
+            let engine = Arc::clone(&engine);
+            thread::spawn(move || -> Result<()> {
+                for i in 0..200 {
+                    let key = format!("key{}-{}", thread, i);
+                    engine.lock().unwrap().set(key.clone(), i.to_string())?;
+                    assert_eq!(engine.lock().unwrap().get(key)?, Some(i.to_string()));
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+
+    let engine = engine.lock().unwrap();
+    for thread in 0..8 {
+        for i in 0..200 {
+            let key = format!("key{}-{}", thread, i);
+            assert_eq!(engine.get(key)?, Some(i.to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn persist_across_reopen<E: KvsEngine>(open: impl Fn() -> Result<E>) -> Result<()> {
+    let mut engine = open()?;
+    engine.set("key1".to_owned(), "value1".to_owned())?;
+    engine.set("key2".to_owned(), "value2".to_owned())?;
+    engine.set("key2".to_owned(), "value3".to_owned())?;
+    engine.set("key3".to_owned(), "value4".to_owned())?;
+    engine.remove("key3".to_owned())?;
+    engine.create_namespace("tenant")?;
+    engine.set_in("tenant", "key1".to_owned(), "tenant".to_owned())?;
+    drop(engine);
+
+    let engine = open()?;
+    assert_eq!(engine.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(engine.get("key2".to_owned())?, Some("value3".to_owned()));
+    assert_eq!(engine.get("key3".to_owned())?, None);
+    assert_eq!(
+        engine.get_in("tenant", "key1".to_owned())?,
+        Some("tenant".to_owned())
+    );
+    Ok(())
+}
+
+// Enough overwrites to trigger the engines' compactions
+fn persist_after_compaction<E: KvsEngine>(open: impl Fn() -> Result<E>) -> Result<()> {
+    let mut engine = open()?;
+    for iter in 0..20 {
+        for i in 0..1000 {
+            engine.set(format!("key{}", i), format!("value{}-{}", i, iter))?;
+        }
+    }
+    for i in (0..1000).step_by(2) {
+        engine.remove(format!("key{}", i))?;
+    }
+    drop(engine);
+
+    let engine = open()?;
+    for i in 0..1000 {
+        let expected = (i % 2 == 1).then(|| format!("value{}-19", i));
+        assert_eq!(engine.get(format!("key{}", i))?, expected);
+    }
+    Ok(())
+}
+
+macro_rules! conformance {
+    ($name:ident, $open:expr) => {
+        mod $name {
+            conformance!(@common $open);
+        }
+    };
+    ($name:ident, $open:expr, persistent) => {
+        mod $name {
+            conformance!(@common $open);
+
+            #[test]
+            fn persist_across_reopen() -> Result<()> {
+                let dir = temp_dir();
+                super::persist_across_reopen(|| open(dir.path()))
+            }
+
+            #[test]
+            fn persist_after_compaction() -> Result<()> {
+                let dir = temp_dir();
+                super::persist_after_compaction(|| open(dir.path()))
+            }
+        }
+    };
+    (@common $open:expr) => {
+        use super::*;
+        use std::path::Path;
+        use tempfile::TempDir;
+
+        fn open(path: &Path) -> Result<impl KvsEngine + Send + 'static> {
+            ($open)(path)
+        }
+
+        fn temp_dir() -> TempDir {
+            TempDir::new().expect("unable to create temporary working directory")
+        }
+
+        #[test]
+        fn get_stored_value() -> Result<()> {
+            let dir = temp_dir();
+            super::get_stored_value(&mut open(dir.path())?)
+        }
+
+        #[test]
+        fn overwrite_value() -> Result<()> {
+            let dir = temp_dir();
+            super::overwrite_value(&mut open(dir.path())?)
+        }
+
+        #[test]
+        fn get_non_existent_value() -> Result<()> {
+            let dir = temp_dir();
+            super::get_non_existent_value(&mut open(dir.path())?)
+        }
+
+        #[test]
+        fn remove_key() -> Result<()> {
+            let dir = temp_dir();
+            super::remove_key(&mut open(dir.path())?)
+        }
+
+        #[test]
+        fn unusual_strings() -> Result<()> {
+            let dir = temp_dir();
+            super::unusual_strings(&mut open(dir.path())?)
+        }
+
+        #[test]
+        fn large_values() -> Result<()> {
+            let dir = temp_dir();
+            super::large_values(&mut open(dir.path())?)
+        }
+
+        #[test]
+        fn scan_prefix() -> Result<()> {
+            let dir = temp_dir();
+            super::scan_prefix(&mut open(dir.path())?)
+        }
+
+        #[test]
+        fn concurrent_access() -> Result<()> {
+            let dir = temp_dir();
+            super::concurrent_access(open(dir.path())?)
+        }
+    };
+}
+
+conformance!(memory, |_: &Path| Ok(MemoryEngine::new()));
+conformance!(kvs_store, KvStore::open, persistent);
+conformance!(sled_engine, SledKvsEngine::open, persistent);
+conformance!(lsm_engine, LsmEngine::open, persistent);
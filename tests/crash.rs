@@ -0,0 +1,444 @@
+//! Crash-consistency tests: `KvStore` runs on a simulated filesystem that
+//! fails at every write point in turn, then drops or tears whatever was not
+//! synced. Recovery must never lose an acknowledged write.
+
+use kvs::{FileSystem, FsFile, KvStore, KvsEngine, SyncPolicy, DEFAULT_NAMESPACE};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const PAGE_SIZE: usize = 4096;
+const KEYS: usize = 16;
+const OPS: usize = 120;
+
+#[derive(Default)]
+struct Node {
+    data: Vec<u8>,
+    // contents as of the last sync
+    durable: Vec<u8>,
+    // whether the directory entry has been synced
+    linked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    /// The operation and every one after it fails, as if the process died.
+    Crash,
+    /// Only the operation fails.
+    Fail,
+}
+
+#[derive(Default)]
+struct State {
+    files: BTreeMap<PathBuf, Node>,
+    // number of mutating operations so far
+    ops: u64,
+    fault: Option<(u64, Fault)>,
+    dead: bool,
+}
+
+impl State {
+    /// Counts a mutating operation and decides whether it fails.
+    fn tick(&mut self) -> io::Result<()> {
+        if self.dead {
+            return Err(io::Error::other("process crashed"));
+        }
+        let op = self.ops;
+        self.ops += 1;
+        match self.fault {
+            Some((at, fault)) if at == op => {
+                self.dead = fault == Fault::Crash;
+                Err(io::Error::other(format!("injected fault at operation {}", op)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn node(&mut self, path: &Path) -> io::Result<&mut Node> {
+        self.files
+            .get_mut(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{:?}", path)))
+    }
+}
+
+/// An in-memory filesystem that only keeps synced data across a `crash`.
+#[derive(Clone, Default)]
+struct SimFs(Arc<Mutex<State>>);
+
+impl SimFs {
+    fn with_fault(at: u64, fault: Fault) -> SimFs {
+        let fs = SimFs::default();
+        fs.0.lock().unwrap().fault = Some((at, fault));
+        fs
+    }
+
+    fn ops(&self) -> u64 {
+        self.0.lock().unwrap().ops
+    }
+
+    /// Loses unsynced files and data. Of the unsynced tail of a file a random
+    /// prefix survives, possibly followed by zeros up to the end of its page
+    /// where the file was extended but the data never reached the disk.
+    fn crash(&self, rng: &mut StdRng) {
+        let mut state = self.0.lock().unwrap();
+        state.files.retain(|_, node| node.linked);
+        for node in state.files.values_mut() {
+            if !node.data.starts_with(&node.durable) {
+                node.data = node.durable.clone();
+                continue;
+            }
+            let unsynced = node.data.len() - node.durable.len();
+            let kept = node.durable.len() + rng.gen_range(0..=unsynced);
+            let extent = if rng.gen_bool(0.5) {
+                (kept.div_ceil(PAGE_SIZE) * PAGE_SIZE).min(node.data.len())
+            } else {
+                kept
+            };
+            node.data.truncate(kept);
+            node.data.resize(extent, 0);
+            node.durable = node.data.clone();
+        }
+        state.fault = None;
+        state.dead = false;
+    }
+}
+
+impl FileSystem for SimFs {
+    fn create_dir_all(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn list_files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let state = self.0.lock().unwrap();
+        Ok(state
+            .files
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn FsFile>> {
+        self.0.lock().unwrap().node(path)?;
+        Ok(Box::new(SimFile {
+            fs: self.clone(),
+            path: path.to_owned(),
+            pos: 0,
+        }))
+    }
+
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn FsFile>> {
+        let mut state = self.0.lock().unwrap();
+        if !state.files.contains_key(path) {
+            state.tick()?;
+            state.files.insert(path.to_owned(), Node::default());
+        }
+        Ok(Box::new(SimFile {
+            fs: self.clone(),
+            path: path.to_owned(),
+            pos: 0,
+        }))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.0.lock().unwrap();
+        state.tick()?;
+        state.node(path)?;
+        state.files.remove(path);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.0.lock().unwrap();
+        state.tick()?;
+        let node = state.files.remove(from).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("{:?}", from))
+        })?;
+        state.files.insert(to.to_owned(), Node { linked: false, ..node });
+        Ok(())
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        let mut state = self.0.lock().unwrap();
+        state.tick()?;
+        for (path, node) in state.files.iter_mut() {
+            if path.parent() == Some(dir) {
+                node.linked = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct SimFile {
+    fs: SimFs,
+    path: PathBuf,
+    pos: u64,
+}
+
+impl Read for SimFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.fs.0.lock().unwrap();
+        let data = &state.node(&self.path)?.data;
+        let start = (self.pos as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for SimFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.fs.0.lock().unwrap();
+        state.tick()?;
+        let data = &mut state.node(&self.path)?.data;
+        let end = self.pos as usize + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[self.pos as usize..end].copy_from_slice(buf);
+        self.pos = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SimFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => pos as i64,
+            SeekFrom::End(offset) => self.size()? as i64 + offset,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+        };
+        if pos < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start"));
+        }
+        self.pos = pos as u64;
+        Ok(self.pos)
+    }
+}
+
+impl FsFile for SimFile {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.fs.0.lock().unwrap().node(&self.path)?.data.len() as u64)
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        let mut state = self.fs.0.lock().unwrap();
+        state.tick()?;
+        state.node(&self.path)?.data.resize(len as usize, 0);
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> io::Result<()> {
+        let mut state = self.fs.0.lock().unwrap();
+        state.tick()?;
+        let node = state.node(&self.path)?;
+        node.durable = node.data.clone();
+        Ok(())
+    }
+}
+
+type Model = BTreeMap<String, BTreeMap<String, String>>;
+
+#[derive(Debug)]
+enum Op {
+    Set { ns: String, key: String, value: String },
+    Remove { ns: String, key: String },
+    CreateNamespace(String),
+    DropNamespace(String),
+    Compact,
+    Reopen,
+}
+
+/// Picks the next operation; only valid ones so that every error is a fault.
+fn next_op(i: usize, model: &Model, rng: &mut StdRng) -> Op {
+    match i {
+        10 => return Op::CreateNamespace("tenant".to_owned()),
+        90 => return Op::DropNamespace("tenant".to_owned()),
+        60 => return Op::Reopen,
+        _ if i % 40 == 39 => return Op::Compact,
+        _ => {}
+    }
+    let ns = if model.contains_key("tenant") && rng.gen_bool(0.3) {
+        "tenant"
+    } else {
+        DEFAULT_NAMESPACE
+    };
+    let live: Vec<&String> = model[ns].keys().collect();
+    if !live.is_empty() && rng.gen_bool(0.3) {
+        return Op::Remove {
+            ns: ns.to_owned(),
+            key: live[rng.gen_range(0..live.len())].clone(),
+        };
+    }
+    // a few values exceed the write buffer so records reach the file in pieces
+    let len = if rng.gen_bool(0.05) { 10_000 } else { rng.gen_range(0..300) };
+    Op::Set {
+        ns: ns.to_owned(),
+        key: format!("key{}", rng.gen_range(0..KEYS)),
+        value: (0..len).map(|_| rng.gen_range(b'a'..=b'z') as char).collect(),
+    }
+}
+
+fn apply(model: &mut Model, op: &Op) {
+    match op {
+        Op::Set { ns, key, value } => {
+            model.get_mut(ns).unwrap().insert(key.clone(), value.clone());
+        }
+        Op::Remove { ns, key } => {
+            model.get_mut(ns).unwrap().remove(key);
+        }
+        Op::CreateNamespace(ns) => {
+            model.insert(ns.clone(), BTreeMap::new());
+        }
+        Op::DropNamespace(ns) => {
+            model.remove(ns);
+        }
+        Op::Compact | Op::Reopen => {}
+    }
+}
+
+fn open(fs: &SimFs, sync: SyncPolicy) -> kvs::Result<KvStore> {
+    KvStore::open_with_fs("db", sync, Arc::new(fs.clone()))
+}
+
+fn execute(store: &mut Option<KvStore>, fs: &SimFs, sync: SyncPolicy, op: &Op) -> kvs::Result<()> {
+    if let Op::Reopen = op {
+        *store = None;
+        *store = Some(open(fs, sync)?);
+        return Ok(());
+    }
+    let store = match store {
+        Some(store) => store,
+        None => {
+            *store = Some(open(fs, sync)?);
+            store.as_mut().unwrap()
+        }
+    };
+    match op {
+        Op::Set { ns, key, value } => store.set_in(ns, key.clone(), value.clone()),
+        Op::Remove { ns, key } => store.remove_in(ns, key.clone()),
+        Op::CreateNamespace(ns) => store.create_namespace(ns),
+        Op::DropNamespace(ns) => store.drop_namespace(ns),
+        Op::Compact => store.compact(),
+        Op::Reopen => unreachable!(),
+    }
+}
+
+fn contents(store: &KvStore) -> Model {
+    let mut model = Model::new();
+    for ns in store.list_namespaces().unwrap() {
+        let mut index = BTreeMap::new();
+        for i in 0..KEYS {
+            let key = format!("key{}", i);
+            if let Some(value) = store.get_in(&ns, key.clone()).unwrap() {
+                index.insert(key, value);
+            }
+        }
+        model.insert(ns, index);
+    }
+    model
+}
+
+/// Runs the workload, returning every state recovery may legally produce.
+///
+/// A failed operation may or may not have taken effect. With `Fault::Crash`
+/// the workload stops there; with `Fault::Fail` it goes on and later
+/// failures come from a store refusing writes, which have no effect.
+fn run_workload(fs: &SimFs, sync: SyncPolicy, fault: Fault) -> Vec<Model> {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut acked = Model::new();
+    acked.insert(DEFAULT_NAMESPACE.to_owned(), BTreeMap::new());
+    // the state had the failed operation taken effect
+    let mut failed: Option<Model> = None;
+    // every acknowledged state, for engines that may lose a suffix
+    let mut history = vec![acked.clone()];
+
+    let mut store = open(fs, sync).ok();
+    if store.is_none() && fault == Fault::Crash {
+        return vec![acked];
+    }
+    for i in 0..OPS {
+        let op = next_op(i, &acked, &mut rng);
+        match execute(&mut store, fs, sync, &op) {
+            Ok(()) => {
+                apply(&mut acked, &op);
+                if let Some(failed) = &mut failed {
+                    apply(failed, &op);
+                }
+            }
+            Err(_) if failed.is_none() => {
+                let mut with_op = acked.clone();
+                apply(&mut with_op, &op);
+                failed = Some(with_op);
+                if fault == Fault::Crash {
+                    break;
+                }
+            }
+            Err(_) => {}
+        }
+        history.push(acked.clone());
+    }
+
+    let mut candidates = match sync {
+        SyncPolicy::Always => vec![acked],
+        _ => history,
+    };
+    candidates.extend(failed);
+    candidates
+}
+
+fn check_recovery(sync: SyncPolicy, fault: Fault) {
+    let total = {
+        let fs = SimFs::default();
+        run_workload(&fs, sync, fault);
+        fs.ops()
+    };
+    assert!(total > OPS as u64);
+
+    for at in 0..total {
+        let fs = SimFs::with_fault(at, fault);
+        let candidates = run_workload(&fs, sync, fault);
+        fs.crash(&mut StdRng::seed_from_u64(at));
+
+        let mut store = open(&fs, sync)
+            .unwrap_or_else(|e| panic!("recovery after fault at {} failed: {}", at, e));
+        let recovered = contents(&store);
+        assert!(
+            candidates.contains(&recovered),
+            "fault at {} recovered an impossible state: {:?}",
+            at,
+            recovered
+        );
+
+        // the recovered store must accept writes and keep them
+        store.set("after".to_owned(), "recovery".to_owned()).unwrap();
+        drop(store);
+        let store = open(&fs, sync).unwrap();
+        assert_eq!(store.get("after".to_owned()).unwrap(), Some("recovery".to_owned()));
+    }
+}
+
+#[test]
+fn crash_at_every_write_keeps_acknowledged_writes() {
+    check_recovery(SyncPolicy::Always, Fault::Crash);
+}
+
+#[test]
+fn failed_syscall_then_crash_keeps_acknowledged_writes() {
+    check_recovery(SyncPolicy::Always, Fault::Fail);
+}
+
+// Without syncing recent writes may be lost, but never out of order
+#[test]
+fn unsynced_crash_recovers_a_prefix() {
+    check_recovery(SyncPolicy::Never, Fault::Crash);
+}
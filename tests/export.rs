@@ -0,0 +1,148 @@
+use assert_cmd::prelude::*;
+use kvs::{
+    export, import, ExportFormat, ExportWriter, ImportReader, KvStore, KvsEngine, KvsError, MemoryEngine, Result,
+};
+use predicates::str::contains;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn populate(engine: &mut impl KvsEngine) -> Result<()> {
+    engine.set("user:1".to_owned(), "alice".to_owned())?;
+    engine.set("user:2".to_owned(), "line\nbreak, \"quoted\"".to_owned())?;
+    engine.set("order:1".to_owned(), "值\0".to_owned())?;
+    engine.create_namespace("tenant")?;
+    engine.set_in("tenant", "user:3".to_owned(), "carol".to_owned())?;
+    Ok(())
+}
+
+// Every format round-trips every namespace between engines
+#[test]
+fn export_import_round_trip() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::open(temp_dir.path())?;
+    populate(&mut store)?;
+
+    for format in [ExportFormat::JsonLines, ExportFormat::Csv] {
+        for base64 in [false, true] {
+            let mut writer = ExportWriter::new(Vec::new(), format, base64);
+            assert_eq!(export(&store, None, "", &mut writer)?, 4);
+            let data = writer.finish()?;
+
+            let mut engine = MemoryEngine::new();
+            assert_eq!(import(&mut engine, ImportReader::new(&data[..], format))?, 4);
+            assert_eq!(engine.list_namespaces()?, store.list_namespaces()?);
+            for ns in store.list_namespaces()? {
+                let expected = store.scan_in(&ns, "")?.collect::<Result<Vec<_>>>()?;
+                assert_eq!(engine.scan_in(&ns, "")?.collect::<Result<Vec<_>>>()?, expected);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn export_prefix() -> Result<()> {
+    let mut engine = MemoryEngine::new();
+    populate(&mut engine)?;
+
+    let mut writer = ExportWriter::new(Vec::new(), ExportFormat::JsonLines, false);
+    assert_eq!(export(&engine, Some("default"), "user:", &mut writer)?, 2);
+    let data = String::from_utf8(writer.finish()?).unwrap();
+    assert_eq!(data.lines().count(), 2);
+    assert!(data.starts_with(r#"{"ns":"default","key":"user:1","value":"alice"}"#));
+
+    let mut writer = ExportWriter::new(Vec::new(), ExportFormat::Csv, false);
+    export(&engine, Some("tenant"), "", &mut writer)?;
+    let data = String::from_utf8(writer.finish()?).unwrap();
+    assert_eq!(data, "ns,key,value\ntenant,user:3,carol\n");
+    Ok(())
+}
+
+#[test]
+fn import_hand_written() -> Result<()> {
+    let mut engine = MemoryEngine::new();
+    let csv = "ns,key,value,encoding\ndefault,key1,dmFsdWUx,base64\nother,key2,value2,\n";
+    assert_eq!(import(&mut engine, ImportReader::new(csv.as_bytes(), ExportFormat::Csv))?, 2);
+    assert_eq!(engine.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(engine.get_in("other", "key2".to_owned())?, Some("value2".to_owned()));
+
+    let jsonl = "{\"ns\":\"default\",\"key\":\"key3\",\"value\":\"value3\"}\n\n";
+    assert_eq!(import(&mut engine, ImportReader::new(jsonl.as_bytes(), ExportFormat::JsonLines))?, 1);
+    assert_eq!(engine.get("key3".to_owned())?, Some("value3".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn import_rejects_invalid_records() {
+    let invalid = [
+        "{\"ns\":\"default\",\"key\":\"a\",\"value\":\"a\"}\n{\"ns\":\"default\",\"key\":\"b\",\"value\":\"!\",\"encoding\":\"base64\"}",
+        "{\"ns\":\"default\",\"key\":\"a\",\"value\":\"a\",\"encoding\":\"rot13\"}",
+        "{\"ns\":\"default\",\"key\":\"a\",\"value\":\"/w==\",\"encoding\":\"base64\"}",
+        "not json",
+    ];
+    for input in invalid {
+        let mut engine = MemoryEngine::new();
+        let result = import(&mut engine, ImportReader::new(input.as_bytes(), ExportFormat::JsonLines));
+        assert!(matches!(result, Err(KvsError::InvalidRecord(_))), "{:?}", input);
+    }
+
+    let mut engine = MemoryEngine::new();
+    let input = invalid[0].as_bytes();
+    match import(&mut engine, ImportReader::new(input, ExportFormat::JsonLines)) {
+        Err(KvsError::InvalidRecord(what)) => assert!(what.starts_with("record 2:"), "{}", what),
+        other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+}
+
+// Values are strings, so base64 only changes how they are written out; bytes
+// that are not UTF-8 cannot be imported
+#[test]
+fn import_base64_is_utf8_only() -> Result<()> {
+    let mut engine = MemoryEngine::new();
+    let valid = "{\"ns\":\"default\",\"key\":\"a\",\"value\":\"5YC8AA==\",\"encoding\":\"base64\"}";
+    import(&mut engine, ImportReader::new(valid.as_bytes(), ExportFormat::JsonLines))?;
+    assert_eq!(engine.get("a".to_owned())?, Some("值\0".to_owned()));
+
+    let binary = "{\"ns\":\"default\",\"key\":\"b\",\"value\":\"/w==\",\"encoding\":\"base64\"}";
+    match import(&mut engine, ImportReader::new(binary.as_bytes(), ExportFormat::JsonLines)) {
+        Err(KvsError::InvalidRecord(what)) => assert!(what.contains("UTF-8"), "{}", what),
+        other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+    assert_eq!(engine.get("b".to_owned())?, None);
+    Ok(())
+}
+
+#[test]
+fn cli_export_import() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let file = temp_dir.path().join("export.csv");
+
+    let mut servers: Vec<_> = ["127.0.0.1:4107", "127.0.0.1:4108"]
+        .iter()
+        .map(|addr| {
+            Command::cargo_bin("kvs-server")
+                .unwrap()
+                .args(["--engine", "memory", "--addr", addr])
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    thread::sleep(Duration::from_secs(1));
+
+    let client = |args: &[&str]| Command::cargo_bin("kvs-client").unwrap().args(args).assert().success();
+    client(&["create-ns", "tenant", "--ipaddr", "127.0.0.1:4107"]);
+    client(&["set", "key1", "value,1", "--ns", "tenant", "--ipaddr", "127.0.0.1:4107"]);
+    client(&["set", "key2", "value2", "--ipaddr", "127.0.0.1:4107"]);
+    client(&["export", "--all", "--format", "csv", "--output", file.to_str().unwrap(), "--ipaddr", "127.0.0.1:4107"]);
+    client(&["import", "--format", "csv", "--input", file.to_str().unwrap(), "--ipaddr", "127.0.0.1:4108"]);
+    client(&["get", "key1", "--ns", "tenant", "--ipaddr", "127.0.0.1:4108"]).stdout(contains("value,1"));
+    client(&["export", "--prefix", "key", "--ipaddr", "127.0.0.1:4108"])
+        .stdout(contains(r#"{"ns":"default","key":"key2","value":"value2"}"#));
+
+    for server in &mut servers {
+        server.kill().expect("server exited before killed");
+        server.wait().unwrap();
+    }
+}
@@ -1,7 +1,8 @@
 use assert_cmd::prelude::*;
 use predicates::str::contains;
 use std::process::Command;
-use kvs::{KvStore, KvsEngine};
+use kvs::{KvStore, KvsEngine, MemoryEngine};
+use tempfile::TempDir;
 
 // `kvs` with no args should exit with a non-zero code.
 #[test]
@@ -14,7 +15,7 @@ fn cli_no_args() {
 fn cli_version() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["-V"])
+        .args(["-V"])
         .assert()
         .stdout(contains(env!("CARGO_PKG_VERSION")));
 }
@@ -24,7 +25,7 @@ fn cli_version() {
 fn cli_get() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get", "key1"])
+        .args(["get", "key1"])
         .assert()
         .failure()
         .stderr(contains("unimplemented"));
@@ -35,7 +36,7 @@ fn cli_get() {
 fn cli_set() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set", "key1", "value1"])
+        .args(["set", "key1", "value1"])
         .assert()
         .failure()
         .stderr(contains("unimplemented"));
@@ -46,7 +47,7 @@ fn cli_set() {
 fn cli_rm() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["rm", "key1"])
+        .args(["rm", "key1"])
         .assert()
         .failure()
         .stderr(contains("unimplemented"));
@@ -56,13 +57,13 @@ fn cli_rm() {
 fn cli_invalid_get() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get"])
+        .args(["get"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get", "extra", "field"])
+        .args(["get", "extra", "field"])
         .assert()
         .failure();
 }
@@ -71,19 +72,19 @@ fn cli_invalid_get() {
 fn cli_invalid_set() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set"])
+        .args(["set"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set", "missing_field"])
+        .args(["set", "missing_field"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set", "extra", "extra", "field"])
+        .args(["set", "extra", "extra", "field"])
         .assert()
         .failure();
 }
@@ -92,13 +93,13 @@ fn cli_invalid_set() {
 fn cli_invalid_rm() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["rm"])
+        .args(["rm"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["rm", "extra", "field"])
+        .args(["rm", "extra", "field"])
         .assert()
         .failure();
 }
@@ -107,7 +108,7 @@ fn cli_invalid_rm() {
 fn cli_invalid_subcommand() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["unknown", "subcommand"])
+        .args(["unknown", "subcommand"])
         .assert()
         .failure();
 }
@@ -115,7 +116,7 @@ fn cli_invalid_subcommand() {
 // Should get previously stored value
 #[test]
 fn get_stored_value() {
-    let mut store = KvStore::new(None);
+    let mut store = MemoryEngine::new();
 
     store.set("key1".to_owned(), "value1".to_owned()).unwrap();
     store.set("key2".to_owned(), "value2".to_owned()).unwrap();
@@ -127,7 +128,7 @@ fn get_stored_value() {
 // Should overwrite existent value
 #[test]
 fn overwrite_value() {
-    let mut store = KvStore::new(None);
+    let mut store = MemoryEngine::new();
 
     store.set("key1".to_owned(), "value1".to_owned()).unwrap();
     assert_eq!(store.get("key1".to_owned()).unwrap(), Some("value1".to_owned()));
@@ -139,7 +140,7 @@ fn overwrite_value() {
 // Should get `None` when getting a non-existent key
 #[test]
 fn get_non_existent_value() {
-    let mut store = KvStore::new(None);
+    let mut store = MemoryEngine::new();
 
     store.set("key1".to_owned(), "value1".to_owned()).unwrap();
     assert_eq!(store.get("key2".to_owned()).unwrap(), None);
@@ -147,17 +148,33 @@ fn get_non_existent_value() {
 
 #[test]
 fn remove_key() {
-    let mut store = KvStore::new(None);
+    let mut store = MemoryEngine::new();
 
     store.set("key1".to_owned(), "value1".to_owned()).unwrap();
     store.remove("key1".to_owned()).unwrap();
     assert_eq!(store.get("key1".to_owned()).unwrap(), None);
 }
 
+// Overwriting one key keeps the log from growing past the stale records
+// compaction allows
 #[test]
 fn compacting_test() {
-    let mut store = KvStore::new(None);
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::open(temp_dir.path()).unwrap();
+    let mut written = 0;
     for i in 0..10004 {
-        store.set(1.to_string(), i.to_string()).unwrap();
+        let value = format!("{:0>100}", i);
+        written += value.len();
+        store.set(1.to_string(), value).unwrap();
     }
+    drop(store);
+
+    let mut size = 0;
+    for entry in std::fs::read_dir(temp_dir.path()).unwrap() {
+        size += entry.unwrap().metadata().unwrap().len();
+    }
+    assert!(size < written as u64, "{} bytes on disk after writing {}", size, written);
+
+    let store = KvStore::open(temp_dir.path()).unwrap();
+    assert_eq!(store.get(1.to_string()).unwrap(), Some(format!("{:0>100}", 10003)));
 }
\ No newline at end of file
@@ -0,0 +1,161 @@
+use kvs::{KvsEngine, KvsError, LsmEngine, LsmOptions, Result};
+use tempfile::TempDir;
+
+fn small_options() -> LsmOptions {
+    LsmOptions {
+        memtable_size: 4 << 10,
+        block_size: 256,
+        table_size: 8 << 10,
+        l0_compaction_trigger: 2,
+        l1_max_bytes: 16 << 10,
+        level_multiplier: 4,
+        ..LsmOptions::default()
+    }
+}
+
+// Should get previously stored value
+#[test]
+fn get_stored_value() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = LsmEngine::open(temp_dir.path())?;
+
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key2".to_owned(), "value2".to_owned())?;
+
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = LsmEngine::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+
+    Ok(())
+}
+
+#[test]
+fn remove_key() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = LsmEngine::open(temp_dir.path())?;
+
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.remove("key1".to_owned())?;
+    assert_eq!(store.get("key1".to_owned())?, None);
+    assert!(store.remove("key1".to_owned()).is_err());
+
+    drop(store);
+    let store = LsmEngine::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, None);
+
+    Ok(())
+}
+
+// Flushes and compactions must keep the newest value of every key
+#[test]
+fn flush_and_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = LsmEngine::open_with_options(temp_dir.path(), small_options())?;
+
+    for round in 0..5 {
+        for i in 0..1000 {
+            store.set(format!("key{}", i), format!("value{}-{}", i, round))?;
+        }
+    }
+    for i in (0..1000).step_by(3) {
+        store.remove(format!("key{}", i))?;
+    }
+
+    let tables = std::fs::read_dir(temp_dir.path().join("sst"))?
+        .filter(|entry| {
+            let path = entry.as_ref().unwrap().path();
+            path.extension() == Some("sst".as_ref())
+        })
+        .count();
+    assert!(tables > 1, "expected memtable flushes, found {} tables", tables);
+
+    for _ in 0..2 {
+        for i in 0..1000 {
+            let expected = (i % 3 != 0).then(|| format!("value{}-4", i));
+            assert_eq!(store.get(format!("key{}", i))?, expected);
+        }
+        drop(store);
+        store = LsmEngine::open_with_options(temp_dir.path(), small_options())?;
+    }
+
+    Ok(())
+}
+
+// Only the tables subdirectory is cleaned up
+#[test]
+fn tables_subdirectory() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = LsmEngine::open_with_options(temp_dir.path(), small_options())?;
+    for i in 0..1000 {
+        store.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    drop(store);
+
+    std::fs::write(temp_dir.path().join("sst").join("999998.sst"), "orphan")?;
+    std::fs::write(temp_dir.path().join("999999.sst"), "not ours")?;
+
+    let store = LsmEngine::open_with_options(temp_dir.path(), small_options())?;
+    for i in 0..1000 {
+        assert_eq!(store.get(format!("key{}", i))?, Some(format!("value{}", i)));
+    }
+    assert!(!temp_dir.path().join("sst").join("999998.sst").exists());
+    assert!(temp_dir.path().join("999999.sst").exists());
+    Ok(())
+}
+
+// A footer pointing past the end of the file is reported, not a panic
+#[test]
+fn corrupt_footer() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = LsmEngine::open_with_options(temp_dir.path(), small_options())?;
+    for i in 0..1000 {
+        store.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    drop(store);
+
+    let table = std::fs::read_dir(temp_dir.path().join("sst"))?.next().unwrap()?.path();
+    let mut data = std::fs::read(&table)?;
+    let footer = data.len() - 20;
+    data[footer..footer + 8].copy_from_slice(&u64::MAX.to_be_bytes());
+    std::fs::write(&table, data)?;
+
+    match LsmEngine::open_with_options(temp_dir.path(), small_options()) {
+        Err(KvsError::Corruption(what)) => assert!(what.contains("bad footer"), "{}", what),
+        other => panic!("unexpected {:?}", other.err()),
+    }
+    Ok(())
+}
+
+// An index claiming more blocks than it holds is refused without allocating
+// room for all of them
+#[test]
+fn corrupt_index_count() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = LsmEngine::open_with_options(temp_dir.path(), small_options())?;
+    for i in 0..1000 {
+        store.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    drop(store);
+
+    let table = std::fs::read_dir(temp_dir.path().join("sst"))?.next().unwrap()?.path();
+    let mut data = std::fs::read(&table)?;
+    let footer = data.len() - 20;
+    let index = u64::from_be_bytes(data[footer..footer + 8].try_into().unwrap()) as usize;
+    let smallest = u32::from_be_bytes(data[index..index + 4].try_into().unwrap()) as usize;
+    let count = index + 4 + smallest;
+    data[count..count + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+    let crc = crc32fast::hash(&data[index..footer - 4]);
+    data[footer - 4..footer].copy_from_slice(&crc.to_be_bytes());
+    std::fs::write(&table, data)?;
+
+    match LsmEngine::open_with_options(temp_dir.path(), small_options()) {
+        Err(KvsError::Corruption(what)) => assert!(what.contains("bad index"), "{}", what),
+        other => panic!("unexpected {:?}", other.err()),
+    }
+    Ok(())
+}
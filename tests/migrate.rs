@@ -0,0 +1,191 @@
+use assert_cmd::prelude::*;
+use common::start_server;
+use kvs::{
+    migrate, verify, Checkpoint, DifferenceKind, KvStore, KvsClient, KvsEngine, KvsError, LsmEngine, MemoryEngine,
+    Result, Scan, SledKvsEngine,
+};
+use std::{fs, process::Command};
+use tempfile::TempDir;
+
+mod common;
+
+fn populate(engine: &mut impl KvsEngine) -> Result<()> {
+    for i in 0..50 {
+        engine.set(format!("key{:02}", i), format!("value{}", i))?;
+    }
+    engine.create_namespace("tenant")?;
+    for i in 0..30 {
+        engine.set_in("tenant", format!("key{:02}", i), format!("tenant{}", i))?;
+    }
+    Ok(())
+}
+
+/// Fails every write after the first `limit`, like a process killed mid-run.
+struct Interrupted {
+    inner: MemoryEngine,
+    writes: usize,
+    limit: usize,
+}
+
+impl KvsEngine for Interrupted {
+    fn set_in(&mut self, ns: &str, key: String, value: String) -> Result<()> {
+        if self.writes == self.limit {
+            return Err(KvsError::Unsupported("interrupted".to_owned()));
+        }
+        self.writes += 1;
+        self.inner.set_in(ns, key, value)
+    }
+
+    fn get_in(&self, ns: &str, key: String) -> Result<Option<String>> {
+        self.inner.get_in(ns, key)
+    }
+
+    fn scan_in<'a>(&'a self, ns: &str, prefix: &str) -> Result<Scan<'a>> {
+        self.inner.scan_in(ns, prefix)
+    }
+
+    fn remove_in(&mut self, ns: &str, key: String) -> Result<()> {
+        self.inner.remove_in(ns, key)
+    }
+
+    fn create_namespace(&mut self, ns: &str) -> Result<()> {
+        self.inner.create_namespace(ns)
+    }
+
+    fn drop_namespace(&mut self, ns: &str) -> Result<()> {
+        self.inner.drop_namespace(ns)
+    }
+
+    fn list_namespaces(&self) -> Result<Vec<String>> {
+        self.inner.list_namespaces()
+    }
+}
+
+#[test]
+fn migrate_between_engines() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let checkpoint = temp_dir.path().join("checkpoint.json");
+    let mut from = KvStore::open(temp_dir.path().join("kvs"))?;
+    populate(&mut from)?;
+    let mut to = SledKvsEngine::open(temp_dir.path().join("sled"))?;
+
+    let progress = migrate(&from, &mut to, &checkpoint, 7)?;
+    assert!(progress.done);
+    assert_eq!(progress.copied, 80);
+    assert!(verify(&from, &to)?.ok);
+    assert_eq!(verify(&from, &to)?.matched, 80);
+
+    // a finished migration is not repeated
+    from.set("late".to_owned(), "value".to_owned())?;
+    assert_eq!(migrate(&from, &mut to, &checkpoint, 7)?, progress);
+    Ok(())
+}
+
+// The resumed run continues after the last checkpoint instead of starting over
+#[test]
+fn migrate_resumes_from_checkpoint() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let checkpoint = temp_dir.path().join("checkpoint.json");
+    let mut from = MemoryEngine::new();
+    populate(&mut from)?;
+
+    let mut to = Interrupted {
+        inner: MemoryEngine::new(),
+        writes: 0,
+        limit: 65,
+    };
+    assert!(migrate(&from, &mut to, &checkpoint, 10).is_err());
+    let saved = Checkpoint::load(&checkpoint)?;
+    assert!(!saved.done);
+    assert_eq!(saved.copied, 60);
+    assert_eq!(saved.ns.as_deref(), Some("tenant"));
+    assert_eq!(saved.key.as_deref(), Some("key09"));
+
+    to.writes = 0;
+    to.limit = usize::MAX;
+    let progress = migrate(&from, &mut to, &checkpoint, 10)?;
+    assert!(progress.done);
+    assert_eq!(progress.copied, 80);
+    assert_eq!(to.writes, 20);
+    assert!(verify(&from, &to)?.ok);
+    Ok(())
+}
+
+#[test]
+fn verify_reports_differences() -> Result<()> {
+    let mut from = MemoryEngine::new();
+    populate(&mut from)?;
+    let mut to = from.clone();
+    to.remove("key01".to_owned())?;
+    to.set("key02".to_owned(), "changed".to_owned())?;
+    to.set("zzz".to_owned(), "extra".to_owned())?;
+    to.drop_namespace("tenant")?;
+    to.create_namespace("other")?;
+
+    let report = verify(&from, &to)?;
+    assert!(!report.ok);
+    assert_eq!(report.matched, 48);
+    assert_eq!(report.mismatched, 1);
+    // the missing namespace counts along with its 30 pairs
+    assert_eq!(report.missing, 1 + 1 + 30);
+    assert_eq!(report.extra, 1 + 1);
+    let kinds: Vec<_> = report.differences.iter().map(|d| (d.key.as_deref(), d.kind)).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (Some("key01"), DifferenceKind::Missing),
+            (Some("key02"), DifferenceKind::Mismatched),
+            (Some("zzz"), DifferenceKind::Extra),
+            (None, DifferenceKind::Missing),
+            (None, DifferenceKind::Extra),
+        ]
+    );
+    Ok(())
+}
+
+// The source keeps serving while it is copied, and the checkpoint stays out
+// of the target store
+#[test]
+fn cli_migrate() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let (from, to) = (temp_dir.path().join("kvs"), temp_dir.path().join("lsm"));
+    populate(&mut KvStore::open(&from)?)?;
+    let (server, addr) = start_server(&["--engine", "kvs", "--threads", "2", "--data-dir", from.to_str().unwrap()]);
+
+    let migrate = |args: &[&str]| {
+        let mut command = Command::cargo_bin("kvs-migrate").unwrap();
+        command.args(["--from", &addr, "--to-engine", "lsm", "--compact"]).args(args).arg("--to").arg(&to);
+        command
+    };
+    let output = migrate(&[]).output().unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["checkpoint"]["copied"], 80);
+    assert_eq!(report["verify"]["ok"], true);
+    assert_eq!(fs::read_to_string(to.join("engine"))?, "lsm");
+    assert!(temp_dir.path().join("lsm.migration.json").exists());
+    assert!(!to.join("migration.json").exists());
+
+    let mut client = KvsClient::connect(&*addr)?;
+    client.set("key00".to_owned(), "changed".to_owned())?;
+    drop(client);
+    migrate(&["--verify-only"]).assert().code(1);
+
+    // a new checkpoint copies the pairs again, changes included
+    let checkpoint = temp_dir.path().join("second.json");
+    migrate(&["--checkpoint", checkpoint.to_str().unwrap()]).assert().success();
+    assert_eq!(LsmEngine::open(&to)?.get("key00".to_owned())?, Some("changed".to_owned()));
+
+    // the target was created by lsm
+    Command::cargo_bin("kvs-migrate")
+        .unwrap()
+        .args(["--from", &addr, "--to-engine", "sled", "--to"])
+        .arg(&to)
+        .assert()
+        .code(2);
+
+    // without a server there is nothing to copy from
+    drop(server);
+    migrate(&[]).assert().code(2);
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! Drives `KvStore` with generated operation sequences and compares every
+//! answer against a `BTreeMap` model. Failing sequences are shrunk by
+//! proptest and recorded under `proptest-regressions/`.
+
+use kvs::{KvStore, KvsEngine, KvsError, DEFAULT_NAMESPACE};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+use tempfile::TempDir;
+
+const NAMESPACES: [&str; 3] = [DEFAULT_NAMESPACE, "a", "b"];
+const KEYS: usize = 8;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Set { ns : &'static str, key : String, value : String },
+    Remove { ns : &'static str, key : String },
+    Get { ns : &'static str, key : String },
+    CreateNamespace { ns : &'static str },
+    DropNamespace { ns : &'static str },
+    Compact,
+    Reopen,
+}
+
+fn ns() -> impl Strategy<Value = &'static str> {
+    prop::sample::select(&NAMESPACES[..])
+}
+
+// A small key space so operations keep hitting the same keys
+fn key() -> impl Strategy<Value = String> {
+    (0..KEYS).prop_map(|i| format!("key{}", i))
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        6 => (ns(), key(), ".{0,32}").prop_map(|(ns, key, value)| Op::Set { ns, key, value }),
+        3 => (ns(), key()).prop_map(|(ns, key)| Op::Remove { ns, key }),
+        3 => (ns(), key()).prop_map(|(ns, key)| Op::Get { ns, key }),
+        1 => ns().prop_map(|ns| Op::CreateNamespace { ns }),
+        1 => ns().prop_map(|ns| Op::DropNamespace { ns }),
+        1 => Just(Op::Compact),
+        1 => Just(Op::Reopen),
+    ]
+}
+
+type Model = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Reduces an engine result to the error variant, which is what the model predicts.
+fn outcome<T>(result : kvs::Result<T>) -> std::result::Result<T, &'static str> {
+    result.map_err(|err| match err {
+        KvsError::KeyNotFound => "key not found",
+        KvsError::NamespaceNotFound(_) => "namespace not found",
+        KvsError::NamespaceExists(_) => "namespace exists",
+        KvsError::InvalidNamespace(_) => "invalid namespace",
+        err => panic!("unexpected engine error: {}", err),
+    })
+}
+
+fn apply(model : &mut Model, op : &Op) -> std::result::Result<Option<String>, &'static str> {
+    match op {
+        Op::Set { ns, key, value } => {
+            let index = model.get_mut(*ns).ok_or("namespace not found")?;
+            index.insert(key.clone(), value.clone());
+            Ok(None)
+        }
+        Op::Remove { ns, key } => {
+            let index = model.get_mut(*ns).ok_or("namespace not found")?;
+            index.remove(key).ok_or("key not found")?;
+            Ok(None)
+        }
+        Op::Get { ns, key } => Ok(model.get(*ns).ok_or("namespace not found")?.get(key).cloned()),
+        Op::CreateNamespace { ns } => {
+            if *ns == DEFAULT_NAMESPACE {
+                return Err("invalid namespace");
+            }
+            if model.contains_key(*ns) {
+                return Err("namespace exists");
+            }
+            model.insert(ns.to_string(), BTreeMap::new());
+            Ok(None)
+        }
+        Op::DropNamespace { ns } => {
+            if *ns == DEFAULT_NAMESPACE {
+                return Err("invalid namespace");
+            }
+            model.remove(*ns).ok_or("namespace not found")?;
+            Ok(None)
+        }
+        Op::Compact | Op::Reopen => Ok(None),
+    }
+}
+
+fn check_contents(store : &KvStore, model : &Model) -> std::result::Result<(), TestCaseError> {
+    prop_assert_eq!(
+        store.list_namespaces().unwrap(),
+        model.keys().cloned().collect::<Vec<_>>()
+    );
+    for (ns, index) in model {
+        for i in 0..KEYS {
+            let key = format!("key{}", i);
+            prop_assert_eq!(store.get_in(ns, key.clone()).unwrap(), index.get(&key).cloned());
+        }
+    }
+    Ok(())
+}
+
+fn run(ops : &[Op]) -> std::result::Result<(), TestCaseError> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::open(temp_dir.path()).unwrap();
+    let mut model = Model::new();
+    model.insert(DEFAULT_NAMESPACE.to_owned(), BTreeMap::new());
+
+    for op in ops {
+        let expected = apply(&mut model, op);
+        let actual = match op {
+            Op::Set { ns, key, value } => outcome(store.set_in(ns, key.clone(), value.clone())).map(|_| None),
+            Op::Remove { ns, key } => outcome(store.remove_in(ns, key.clone())).map(|_| None),
+            Op::Get { ns, key } => outcome(store.get_in(ns, key.clone())),
+            Op::CreateNamespace { ns } => outcome(store.create_namespace(ns)).map(|_| None),
+            Op::DropNamespace { ns } => outcome(store.drop_namespace(ns)).map(|_| None),
+            Op::Compact => {
+                store.compact().unwrap();
+                check_contents(&store, &model)?;
+                Ok(None)
+            }
+            Op::Reopen => {
+                drop(store);
+                store = KvStore::open(temp_dir.path()).unwrap();
+                check_contents(&store, &model)?;
+                Ok(None)
+            }
+        };
+        prop_assert_eq!(actual, expected, "after {:?}", op);
+    }
+
+    // whatever survived must also survive one more reopen
+    drop(store);
+    let store = KvStore::open(temp_dir.path()).unwrap();
+    check_contents(&store, &model)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    #[test]
+    fn kv_store_matches_model(ops in vec(op(), 1..200)) {
+        run(&ops)?;
+    }
+}
@@ -0,0 +1,115 @@
+use kvs::{
+    KvStore, KvsEngine, KvsError, LsmEngine, LsmOptions, MemoryEngine, Result, SledKvsEngine,
+    DEFAULT_NAMESPACE,
+};
+use tempfile::TempDir;
+
+fn check_namespaces(engine: &mut impl KvsEngine) -> Result<()> {
+    engine.set("key1".to_owned(), "default".to_owned())?;
+    engine.create_namespace("tenant")?;
+    assert!(matches!(
+        engine.create_namespace("tenant"),
+        Err(KvsError::NamespaceExists(_))
+    ));
+    engine.set_in("tenant", "key1".to_owned(), "tenant".to_owned())?;
+
+    assert_eq!(engine.get("key1".to_owned())?, Some("default".to_owned()));
+    assert_eq!(
+        engine.get_in("tenant", "key1".to_owned())?,
+        Some("tenant".to_owned())
+    );
+    assert_eq!(
+        engine.list_namespaces()?,
+        vec![DEFAULT_NAMESPACE.to_owned(), "tenant".to_owned()]
+    );
+
+    engine.remove_in("tenant", "key1".to_owned())?;
+    assert_eq!(engine.get_in("tenant", "key1".to_owned())?, None);
+    assert_eq!(engine.get("key1".to_owned())?, Some("default".to_owned()));
+
+    engine.set_in("tenant", "key2".to_owned(), "tenant".to_owned())?;
+    engine.drop_namespace("tenant")?;
+    assert!(matches!(
+        engine.get_in("tenant", "key2".to_owned()),
+        Err(KvsError::NamespaceNotFound(_))
+    ));
+    assert!(matches!(
+        engine.set_in("missing", "key1".to_owned(), "value".to_owned()),
+        Err(KvsError::NamespaceNotFound(_))
+    ));
+    assert!(matches!(
+        engine.drop_namespace(DEFAULT_NAMESPACE),
+        Err(KvsError::InvalidNamespace(_))
+    ));
+
+    // A recreated namespace starts out empty
+    engine.create_namespace("tenant")?;
+    assert_eq!(engine.get_in("tenant", "key2".to_owned())?, None);
+
+    Ok(())
+}
+
+#[test]
+fn memory_namespaces() -> Result<()> {
+    check_namespaces(&mut MemoryEngine::new())
+}
+
+#[test]
+fn sled_namespaces() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    check_namespaces(&mut SledKvsEngine::open(temp_dir.path())?)
+}
+
+#[test]
+fn kvs_namespaces() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::open(temp_dir.path())?;
+    check_namespaces(&mut store)?;
+    store.set_in("tenant", "key3".to_owned(), "tenant".to_owned())?;
+
+    // Namespaces survive both compaction and reopening
+    store.compact()?;
+    drop(store);
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(
+        store.get_in("tenant", "key3".to_owned())?,
+        Some("tenant".to_owned())
+    );
+    assert_eq!(store.get("key1".to_owned())?, Some("default".to_owned()));
+
+    Ok(())
+}
+
+#[test]
+fn lsm_namespaces() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = LsmOptions {
+        memtable_size: 1 << 10,
+        l0_compaction_trigger: 2,
+        ..LsmOptions::default()
+    };
+    let mut store = LsmEngine::open_with_options(temp_dir.path(), options.clone())?;
+    check_namespaces(&mut store)?;
+
+    // Keys of a dropped namespace must not reappear after flushes and compactions
+    store.create_namespace("dropped")?;
+    for i in 0..200 {
+        store.set_in("dropped", format!("key{}", i), "value".to_owned())?;
+    }
+    store.drop_namespace("dropped")?;
+    store.create_namespace("dropped")?;
+    for i in 0..200 {
+        store.set_in("tenant", format!("key{}", i), "value".to_owned())?;
+    }
+
+    drop(store);
+    let store = LsmEngine::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.get_in("dropped", "key1".to_owned())?, None);
+    assert_eq!(
+        store.get_in("tenant", "key199".to_owned())?,
+        Some("value".to_owned())
+    );
+    assert_eq!(store.get("key1".to_owned())?, Some("default".to_owned()));
+
+    Ok(())
+}
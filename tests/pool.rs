@@ -0,0 +1,139 @@
+use common::{free_addr, server_command, spawn_server, ServerGuard};
+use kvs::{Features, Hello, Idempotency, KvsClientPool, KvsError, PoolOptions};
+use std::io;
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+
+fn start_server(addr: &str) -> ServerGuard {
+    spawn_server(&mut server_command(addr, &["--engine", "memory", "--threads", "4"]), addr)
+}
+
+fn options() -> PoolOptions {
+    PoolOptions {
+        size: 2,
+        initial_backoff: Duration::from_millis(10),
+        ..PoolOptions::default()
+    }
+}
+
+// Threads share the pool's connections, and a request that cannot get one
+// in time fails at its deadline
+#[test]
+fn pool_shares_connections() {
+    let addr = free_addr();
+    let server = start_server(&addr);
+
+    let pool = Arc::new(KvsClientPool::new(&*addr, options()).unwrap());
+    let threads: Vec<_> = (0..8)
+        .map(|t| {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || {
+                for i in 0..50 {
+                    pool.set(format!("key{}-{}", t, i), format!("value{}", i)).unwrap();
+                    assert_eq!(pool.get(format!("key{}-{}", t, i)).unwrap(), Some(format!("value{}", i)));
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    pool.remove("key0-0".to_owned()).unwrap();
+    assert!(matches!(pool.remove("key0-0".to_owned()), Err(KvsError::KeyNotFound)));
+
+    // hold both connections for a second
+    let holders: Vec<_> = (0..2)
+        .map(|_| {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                pool.execute(Idempotency::Idempotent, deadline, |client| {
+                    thread::sleep(Duration::from_secs(1));
+                    client.get("key1-1".to_owned())
+                })
+                .unwrap()
+            })
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(200));
+    let start = Instant::now();
+    let deadline = start + Duration::from_millis(200);
+    match pool.execute(Idempotency::Idempotent, deadline, |client| client.get("key1-1".to_owned())) {
+        Err(KvsError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+        result => panic!("expected a timeout, got {:?}", result.map_err(|e| e.to_string())),
+    }
+    assert!(start.elapsed() < Duration::from_millis(700));
+    for holder in holders {
+        assert_eq!(holder.join().unwrap(), Some("value1".to_owned()));
+    }
+
+    drop(server);
+}
+
+// Connections the server closed are replaced without spending a retry
+#[test]
+fn pool_replaces_closed_connections() {
+    let addr = free_addr();
+    let server = start_server(&addr);
+
+    let pool = KvsClientPool::new(&*addr, PoolOptions { max_retries: 0, ..options() }).unwrap();
+    pool.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    drop(server);
+    let server = start_server(&addr);
+    assert_eq!(pool.get("key1".to_owned()).unwrap(), None);
+
+    // requests that never reached the server are retried, even removals
+    drop(server);
+    let restart_addr = addr.clone();
+    let restart = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(300));
+        start_server(&restart_addr)
+    });
+    let pool = KvsClientPool::new(
+        &*addr,
+        PoolOptions {
+            max_retries: 20,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(200),
+            ..options()
+        },
+    )
+    .unwrap();
+    assert!(matches!(pool.remove("key1".to_owned()), Err(KvsError::KeyNotFound)));
+    drop(restart.join().unwrap());
+}
+
+// A server dropping the connection after reading a request gets the
+// request again only if it is idempotent
+#[test]
+fn pool_retries_idempotent_requests() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let received = Arc::clone(&requests);
+    thread::spawn(move || {
+        for conn in listener.incoming() {
+            let mut conn = conn.unwrap();
+            kvs::read_frame(&mut conn, KvsError::InvalidRequest).unwrap();
+            kvs::write_frame(&mut conn, &Hello::new(Features::empty()).build(), KvsError::InvalidReply).unwrap();
+            if kvs::read_frame(&mut conn, KvsError::InvalidRequest).is_ok() {
+                received.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    });
+
+    let pool = KvsClientPool::new(addr, options()).unwrap();
+    assert!(matches!(pool.get("key1".to_owned()), Err(KvsError::Io(_))));
+    assert_eq!(requests.swap(0, Ordering::SeqCst), 4);
+    assert!(matches!(pool.set("key1".to_owned(), "value1".to_owned()), Err(KvsError::Io(_))));
+    assert_eq!(requests.swap(0, Ordering::SeqCst), 4);
+    assert!(matches!(pool.remove("key1".to_owned()), Err(KvsError::Io(_))));
+    assert_eq!(requests.swap(0, Ordering::SeqCst), 1);
+
+    assert!(KvsClientPool::new(addr, PoolOptions { size: 0, ..options() }).is_err());
+}
@@ -0,0 +1,234 @@
+//! Feeds the request and reply parsers generated and hand-made malformed
+//! frames. The `fuzz/` targets explore the same properties with libFuzzer.
+
+use assert_cmd::prelude::*;
+use kvs::{
+    ErrorCode, Features, Hello, KvsError, ReplyMsg, ReplyType, RequestMsg, RequestType, ScanCursor, ScanPage, MAX_FRAME_LEN,
+    PROTOCOL_VERSION,
+};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::io::Write;
+use std::net::TcpStream;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+fn request_type(i : u8) -> RequestType {
+    match i {
+        0 => RequestType::Get,
+        1 => RequestType::Put,
+        2 => RequestType::Delete,
+        _ => RequestType::Scan,
+    }
+}
+
+proptest! {
+    #[test]
+    fn parse_never_panics(data in vec(any::<u8>(), 0..64)) {
+        if let Ok(msg) = RequestMsg::parse(&data) {
+            prop_assert_eq!(RequestMsg::build(msg.request_type, &msg.namespace, msg.key, msg.value), data.clone());
+        }
+        if let Ok(msg) = ReplyMsg::parse(&data) {
+            prop_assert_eq!(msg.build(), data.clone());
+        }
+        if let Some(hello) = Hello::parse(&data) {
+            prop_assert_eq!(hello.build(), data);
+        }
+    }
+
+    // Cutting a valid frame anywhere leaves a malformed one
+    #[test]
+    fn truncated_requests_are_rejected(t in 0..4u8, ns in ".{0,8}", key in ".{0,8}", value in ".{0,8}") {
+        let value = if t == 1 || t == 3 { Some(value) } else { None };
+        let frame = RequestMsg::build(request_type(t), &ns, key.clone(), value.clone());
+        let msg = RequestMsg::parse(&frame).unwrap();
+        prop_assert_eq!((msg.namespace, msg.key, msg.value), (ns, key, value));
+        for len in 0..frame.len() {
+            prop_assert!(matches!(RequestMsg::parse(&frame[..len]), Err(KvsError::InvalidRequest)));
+        }
+    }
+}
+
+#[test]
+fn malformed_frames() {
+    let requests : [&[u8]; 6] = [
+        &[],
+        &[0x9, 0, 0, 0, 0, 0, 0, 0, 0],
+        &[0x1, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0],
+        &[0x1, 0, 0, 0, 1, 0xff, 0, 0, 0, 0],
+        &[0x1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        &[0x2, 0, 0, 0, 0, 0, 0, 0, 0],
+    ];
+    for frame in requests {
+        assert!(matches!(RequestMsg::parse(frame), Err(KvsError::InvalidRequest)), "{:?}", frame);
+    }
+
+    let replies : [&[u8]; 7] = [
+        &[],
+        &[0x4],
+        &[0x2, 0],
+        &[0x3, 0, 0, 0, 2, b'a'],
+        &[0x1],
+        &[0x1, 0, 0x1],
+        &[0x1, 0, 0x20, 0, 0, 0, 0],
+    ];
+    for frame in replies {
+        assert!(matches!(ReplyMsg::parse(frame), Err(KvsError::InvalidReply)), "{:?}", frame);
+    }
+
+    // a missing key is answered with a value-less message
+    let reply = ReplyMsg::parse(&[0x3]).unwrap();
+    assert!(matches!(reply.reply_type, ReplyType::Msg));
+    assert_eq!(reply.value, None);
+
+    let reply = ReplyMsg::parse(&ReplyMsg::error(&KvsError::NamespaceExists("a".to_owned())).build()).unwrap();
+    assert_eq!(reply.code, Some(ErrorCode::Conflict));
+    assert_eq!(reply.value.as_deref(), Some("namespace already exists: a"));
+
+    let hello = Hello::new(Features::PIPELINING);
+    assert_eq!(Hello::parse(&hello.build()), Some(hello));
+    assert_eq!(Hello::parse(&hello.build()[..8]), None);
+    assert_eq!(Hello::parse(b"KVT\0\x01\0\0\0\0"), None);
+    assert!(hello.features.contains(Features::PIPELINING));
+    assert!(!Hello::new(Features::empty()).features.contains(Features::PIPELINING));
+
+    let oversized = vec![0; MAX_FRAME_LEN + 1];
+    assert!(matches!(kvs::write_frame(&mut Vec::new(), &oversized, KvsError::InvalidRequest), Err(KvsError::InvalidRequest)));
+    // a bogus length is refused without allocating for it
+    let len = (MAX_FRAME_LEN as u32 + 1).to_be_bytes();
+    assert!(matches!(kvs::read_frame(&mut &len[..], KvsError::InvalidReply), Err(KvsError::InvalidReply)));
+    assert!(matches!(kvs::read_frame(&mut &[0xff; 8][..], KvsError::InvalidRequest), Err(KvsError::InvalidRequest)));
+}
+
+fn connect() -> TcpStream {
+    let mut conn = TcpStream::connect("127.0.0.1:4109").unwrap();
+    let hello = kvs::handshake(&mut conn, Features::PIPELINING).unwrap();
+    assert_eq!(hello, Hello::new(Features::PIPELINING));
+    conn
+}
+
+/// Sends a frame of `frame_len` announced bytes and returns the message of
+/// the reply, dropping its request id.
+fn exchange(mut conn : TcpStream, frame_len : u32, frame : &[u8]) -> Vec<u8> {
+    conn.write_all(&frame_len.to_be_bytes()).unwrap();
+    conn.write_all(frame).unwrap();
+    let reply = kvs::read_frame(&mut conn, KvsError::InvalidReply).unwrap();
+    kvs::split_request_id(&reply).unwrap().1.to_vec()
+}
+
+/// Sends `msg` on a new connection and returns the message of the reply.
+fn request(msg : &[u8]) -> Vec<u8> {
+    let frame = kvs::with_request_id(1, msg);
+    exchange(connect(), frame.len() as u32, &frame)
+}
+
+fn scan(prefix : &str, after : Option<&str>, limit : u32) -> Vec<u8> {
+    let cursor = ScanCursor { after : after.map(str::to_owned), limit };
+    request(&RequestMsg::build(RequestType::Scan, "default", prefix.to_owned(), Some(serde_json::to_string(&cursor).unwrap())))
+}
+
+fn scan_page(reply : &[u8]) -> (Vec<String>, bool) {
+    let page : ScanPage = serde_json::from_str(&ReplyMsg::parse(reply).unwrap().value.unwrap()).unwrap();
+    (page.pairs.into_iter().map(|(key, _)| key).collect(), page.more)
+}
+
+fn error_message(reply : &[u8]) -> String {
+    let reply = ReplyMsg::parse(reply).unwrap();
+    assert!(matches!(reply.reply_type, ReplyType::Error));
+    reply.value.unwrap()
+}
+
+// Malformed and oversized frames get an error reply and the server keeps serving
+#[test]
+fn server_rejects_malformed_frames() {
+    let mut server = Command::cargo_bin("kvs-server")
+        .unwrap()
+        .args(["--engine", "memory", "--addr", "127.0.0.1:4109"])
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    assert_eq!(error_message(&exchange(connect(), 3, &[0x1, 0, 0])), "invalid request");
+    assert_eq!(error_message(&exchange(connect(), 0, &[])), "invalid request");
+    assert_eq!(error_message(&exchange(connect(), MAX_FRAME_LEN as u32 + 1, &[])), "invalid request");
+
+    let set = RequestMsg::build(RequestType::Put, "default", "key".to_owned(), Some("value".to_owned()));
+    assert_eq!(request(&set), [ReplyType::Ok as u8]);
+    let get = RequestMsg::build(RequestType::Get, "default", "key".to_owned(), None);
+    let reply = request(&get);
+    assert_eq!(ReplyMsg::parse(&reply).unwrap().value.as_deref(), Some("value"));
+
+    // scans come in pages of at most the asked length
+    for key in ["key1", "key2"] {
+        let set = RequestMsg::build(RequestType::Put, "default", key.to_owned(), Some("value".to_owned()));
+        request(&set);
+    }
+    assert_eq!(scan_page(&scan("key", None, 2)), (vec!["key".to_owned(), "key1".to_owned()], true));
+    assert_eq!(scan_page(&scan("key", Some("key1"), 2)), (vec!["key2".to_owned()], false));
+    assert_eq!(error_message(&scan("key", None, 0)), "invalid request");
+
+    // a page too long for a frame stops early, with more to come
+    let large = "x".repeat(MAX_FRAME_LEN / 2);
+    for key in ["large1", "large2"] {
+        let set = RequestMsg::build(RequestType::Put, "default", key.to_owned(), Some(large.clone()));
+        request(&set);
+    }
+    assert_eq!(scan_page(&scan("large", None, 2)), (vec!["large1".to_owned()], true));
+    assert_eq!(scan_page(&scan("large", Some("large1"), 2)), (vec!["large2".to_owned()], false));
+
+    // a client sending its request without a handshake is told to upgrade
+    let mut conn = TcpStream::connect("127.0.0.1:4109").unwrap();
+    kvs::write_frame(&mut conn, &get, KvsError::InvalidRequest).unwrap();
+    assert!(error_message(&kvs::read_frame(&mut conn, KvsError::InvalidReply).unwrap()).contains("expected a handshake"));
+
+    let mut conn = TcpStream::connect("127.0.0.1:4109").unwrap();
+    let ancient = Hello { version : 0, features : Features::empty() };
+    kvs::write_frame(&mut conn, &ancient.build(), KvsError::InvalidRequest).unwrap();
+    assert!(error_message(&kvs::read_frame(&mut conn, KvsError::InvalidReply).unwrap()).contains("protocol version 0"));
+
+    let mut conn = TcpStream::connect("127.0.0.1:4109").unwrap();
+    let future = Hello { version : PROTOCOL_VERSION + 1, features : Features::empty() };
+    kvs::write_frame(&mut conn, &future.build(), KvsError::InvalidRequest).unwrap();
+    let message = error_message(&kvs::read_frame(&mut conn, KvsError::InvalidReply).unwrap());
+    assert!(message.contains(&format!("client speaks protocol version {}", PROTOCOL_VERSION + 1)));
+
+    // features the server does not know are dropped from the answer
+    let mut conn = TcpStream::connect("127.0.0.1:4109").unwrap();
+    let greedy = Hello { version : PROTOCOL_VERSION, features : Features::from_bits(0xff) };
+    kvs::write_frame(&mut conn, &greedy.build(), KvsError::InvalidRequest).unwrap();
+    assert_eq!(Hello::parse(&kvs::read_frame(&mut conn, KvsError::InvalidReply).unwrap()), Some(Hello::new(Features::PIPELINING)));
+    // the connection stays open for requests until the client closes it
+    drop(conn);
+
+    // pipelined requests are answered in order with their ids, and a
+    // malformed one among them does not end the connection
+    let mut conn = connect();
+    for i in 0..10 {
+        let msg = match i {
+            5 => vec![0x9],
+            _ => RequestMsg::build(RequestType::Put, "default", format!("pipelined{}", i), Some(format!("value{}", i))),
+        };
+        kvs::write_frame(&mut conn, &kvs::with_request_id(100 + i, &msg), KvsError::InvalidRequest).unwrap();
+    }
+    let get = RequestMsg::build(RequestType::Get, "default", "pipelined9".to_owned(), None);
+    kvs::write_frame(&mut conn, &kvs::with_request_id(7, &get), KvsError::InvalidRequest).unwrap();
+    for i in 0..10 {
+        let reply = kvs::read_frame(&mut conn, KvsError::InvalidReply).unwrap();
+        let (id, msg) = kvs::split_request_id(&reply).unwrap();
+        assert_eq!(id, 100 + i);
+        match i {
+            5 => assert_eq!(error_message(msg), "invalid request"),
+            _ => assert_eq!(msg, [ReplyType::Ok as u8]),
+        }
+    }
+    let reply = kvs::read_frame(&mut conn, KvsError::InvalidReply).unwrap();
+    let (id, msg) = kvs::split_request_id(&reply).unwrap();
+    assert_eq!(id, 7);
+    assert_eq!(ReplyMsg::parse(msg).unwrap().value.as_deref(), Some("value9"));
+    drop(conn);
+    assert_eq!(ReplyMsg::parse(&request(&get)).unwrap().value.as_deref(), Some("value9"));
+
+    server.kill().expect("server exited before killed");
+    server.wait().unwrap();
+}
@@ -0,0 +1,261 @@
+use assert_cmd::prelude::*;
+use common::{free_addr, server_command, spawn_server, start_server};
+use kvs::{Features, KvStore, KvsEngine, LsmEngine, SledKvsEngine, ENGINE_MARKER};
+use predicates::str::contains;
+use std::io::Read;
+use std::net::TcpStream;
+use std::process::Command;
+use std::time::Duration;
+use tempfile::TempDir;
+
+mod common;
+
+// `kvs-server` should refuse to open a directory created by another engine
+#[test]
+fn server_rejects_different_engine() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    let addr = free_addr();
+    drop(spawn_server(server_command(&addr, &["--engine", "kvs"]).current_dir(&temp_dir), &addr));
+
+    server_command(&free_addr(), &["--engine", "lsm"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("refusing"));
+}
+
+// Directories from before the marker are recognized by their files
+#[test]
+fn server_detects_unmarked_engine() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::open(temp_dir.path()).unwrap();
+    store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    drop(store);
+
+    server_command(&free_addr(), &["--engine", "sled"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("created by the kvs engine"));
+    assert!(!temp_dir.path().join(ENGINE_MARKER).exists());
+    assert_eq!(kvs::detect_engine(temp_dir.path()).unwrap().as_deref(), Some("kvs"));
+}
+
+#[test]
+fn detect_engine_files() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let dir = |name: &str| temp_dir.path().join(name);
+    drop(SledKvsEngine::open(dir("sled")).unwrap());
+    drop(LsmEngine::open(dir("lsm")).unwrap());
+    std::fs::create_dir(dir("empty")).unwrap();
+
+    assert_eq!(kvs::detect_engine(&dir("sled")).unwrap().as_deref(), Some("sled"));
+    assert_eq!(kvs::detect_engine(&dir("lsm")).unwrap().as_deref(), Some("lsm"));
+    assert_eq!(kvs::detect_engine(&dir("empty")).unwrap(), None);
+    assert_eq!(kvs::detect_engine(&dir("missing")).unwrap(), None);
+
+    std::fs::write(dir("lsm").join("1.log"), "").unwrap();
+    assert!(kvs::detect_engine(&dir("lsm")).is_err());
+    kvs::write_engine_marker(&dir("lsm"), "lsm").unwrap();
+    assert_eq!(kvs::read_engine_marker(&dir("lsm")).unwrap().as_deref(), Some("lsm"));
+    assert_eq!(kvs::detect_engine(&dir("lsm")).unwrap().as_deref(), Some("lsm"));
+}
+
+// A failed open must not leave a marker behind
+#[test]
+fn server_marks_only_opened_engine() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    std::fs::write(temp_dir.path().join("MANIFEST"), "not json").unwrap();
+
+    server_command(&free_addr(), &["--engine", "lsm"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("Could not open engine"));
+    assert!(!temp_dir.path().join(ENGINE_MARKER).exists());
+}
+
+#[test]
+fn server_rejects_unknown_engine() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    server_command(&free_addr(), &["--engine", "unknown"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure();
+}
+
+// `--data-dir` should be honored by every engine
+#[test]
+fn server_sled_data_dir() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let data_dir = temp_dir.path().join("data");
+
+    let data_dir_arg = data_dir.to_str().unwrap();
+    let (server, addr) = start_server(&["--engine", "sled", "--data-dir", data_dir_arg]);
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--ipaddr", &addr])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key1", "--ipaddr", &addr])
+        .assert()
+        .success()
+        .stdout(contains("value1"));
+
+    drop(server);
+    let engine = std::fs::read_to_string(data_dir.join("engine")).unwrap();
+    assert_eq!(engine, "sled");
+}
+
+// Engine errors should be answered with an error reply while the server keeps running
+#[test]
+fn server_replies_engine_errors() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let db = sled::open(temp_dir.path()).unwrap();
+    db.insert("bad", &[0xff, 0xfe][..]).unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    let addr = free_addr();
+    let server = spawn_server(server_command(&addr, &["--engine", "sled"]).current_dir(&temp_dir), &addr);
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "bad", "--ipaddr", &addr])
+        .assert()
+        .code(19)
+        .stderr(contains("error: UTF-8 error"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["rm", "missing", "--ipaddr", &addr])
+        .assert()
+        .code(11)
+        .stderr(contains("error: key not found"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key1", "--ns", "missing", "--ipaddr", &addr])
+        .assert()
+        .code(12)
+        .stderr(contains("namespace not found: missing"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["create-ns", "x", "--ipaddr", &addr])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["create-ns", "x", "--ipaddr", &addr])
+        .assert()
+        .code(13)
+        .stderr(contains("namespace already exists: x"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["create-ns", "", "--ipaddr", &addr])
+        .assert()
+        .code(14)
+        .stderr(contains("invalid namespace"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--ipaddr", &addr])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key1", "--ipaddr", &addr])
+        .assert()
+        .stdout(contains("value1"));
+    // backups are off unless the server was given a root for them
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["backup", "backup", "--ipaddr", &addr])
+        .assert()
+        .code(15)
+        .stderr(contains("start the server with --backup-root"));
+
+    drop(server);
+    // with the server gone the connection fails
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key1", "--ipaddr", &addr])
+        .assert()
+        .code(17);
+}
+
+// A sled I/O error during a request reaches the client as an error reply.
+// Ignoring SIGXFSZ and limiting the file size to zero makes every write
+// sled attempts fail with EFBIG.
+#[cfg(unix)]
+#[test]
+fn server_replies_sled_io_errors() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    // a killed server leaves a database that reopens without writing
+    let addr = free_addr();
+    drop(spawn_server(server_command(&addr, &["--engine", "sled"]).current_dir(&temp_dir), &addr));
+
+    let server = spawn_server(
+        Command::new("sh")
+            .arg("-c")
+            .arg("trap '' XFSZ; ulimit -f 0; exec \"$0\" --engine sled --addr \"$1\"")
+            .arg(assert_cmd::cargo::cargo_bin("kvs-server"))
+            .arg(&addr)
+            .current_dir(&temp_dir),
+        &addr,
+    );
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--ipaddr", &addr])
+        .assert()
+        .code(19)
+        .stderr(contains("sled error"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["list-ns", "--ipaddr", &addr])
+        .assert()
+        .success();
+    drop(server);
+}
+
+// Clients keeping their connections open do not stall the others for
+// longer than the idle timeout
+#[test]
+fn server_serves_connections_concurrently() {
+    for pool in ["naive", "shared-queue", "work-stealing"] {
+        let args = ["--engine", "memory", "--threads", "2", "--idle-timeout", "1", "--pool", pool];
+        let (server, addr) = start_server(&args);
+
+        let mut idle: Vec<_> = (0..2).map(|_| TcpStream::connect(&addr).unwrap()).collect();
+        for conn in &mut idle {
+            kvs::handshake(conn, Features::PIPELINING).unwrap();
+        }
+        // fails instead of hanging if the idle connections held every thread
+        assert_cmd::Command::cargo_bin("kvs-client")
+            .unwrap()
+            .args(["set", "key1", pool, "--ipaddr", &addr])
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success();
+        assert_cmd::Command::cargo_bin("kvs-client")
+            .unwrap()
+            .args(["get", "key1", "--ipaddr", &addr])
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+            .stdout(contains(pool));
+        // the server closed the idle connections
+        for mut conn in idle {
+            conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            assert_eq!(conn.read(&mut [0; 1]).unwrap(), 0);
+        }
+        drop(server);
+    }
+
+    server_command(&free_addr(), &["--engine", "memory", "--threads", "0"])
+        .assert()
+        .code(2);
+}
@@ -0,0 +1,66 @@
+use common::{free_addr, server_command, spawn_server};
+use kvs::{ClientOptions, KvStore, KvsClient, KvsEngine};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+mod common;
+
+/// Signals a server busy with writers and checks that it exits cleanly and
+/// that every write it acknowledged is in the store.
+fn shutdown_keeps_acknowledged_writes(signal: &str, args: &[&str]) {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = free_addr();
+    let mut server = spawn_server(
+        server_command(&addr, &["--engine", "kvs", "--threads", "4"]).args(args).current_dir(&temp_dir),
+        &addr,
+    );
+
+    let writers: Vec<_> = (0..4)
+        .map(|t| {
+            let addr = addr.clone();
+            thread::spawn(move || {
+                let options = ClientOptions {
+                    io_timeout: Some(Duration::from_secs(5)),
+                    ..ClientOptions::default()
+                };
+                let mut client = KvsClient::connect_with_options(&*addr, options).unwrap();
+                // the writes acknowledged before the server went away
+                (0..).take_while(|i| client.set(format!("key{}-{}", t, i), format!("value{}", i)).is_ok()).count()
+            })
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(500));
+
+    let pid = server.0.id().to_string();
+    assert!(Command::new("kill").args(["-s", signal, &pid]).status().unwrap().success());
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = server.0.try_wait().unwrap() {
+            break status;
+        }
+        assert!(start.elapsed() < Duration::from_secs(10), "server still running after {}", signal);
+        thread::sleep(Duration::from_millis(50));
+    };
+    assert!(status.success(), "server exited with {}", status);
+
+    let acknowledged: Vec<usize> = writers.into_iter().map(|writer| writer.join().unwrap()).collect();
+    let store = KvStore::open(temp_dir.path()).unwrap();
+    for (t, &count) in acknowledged.iter().enumerate() {
+        assert!(count > 0, "writer {} got no write through", t);
+        for i in 0..count {
+            assert_eq!(store.get(format!("key{}-{}", t, i)).unwrap(), Some(format!("value{}", i)));
+        }
+    }
+}
+
+#[test]
+fn shutdown_on_sigterm() {
+    shutdown_keeps_acknowledged_writes("TERM", &[]);
+}
+
+#[test]
+fn shutdown_async_on_sigint() {
+    shutdown_keeps_acknowledged_writes("INT", &["--async"]);
+}
@@ -0,0 +1,54 @@
+use kvs::{KvsEngine, KvsError, Result, SledKvsEngine, SyncPolicy};
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn get_stored_value() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = SledKvsEngine::open_with_sync(
+        temp_dir.path(),
+        SyncPolicy::Interval(Duration::from_millis(10)),
+    )?;
+
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    store.remove("key1".to_owned())?;
+    assert!(matches!(
+        store.remove("key1".to_owned()),
+        Err(KvsError::KeyNotFound)
+    ));
+
+    Ok(())
+}
+
+// A value that is not valid UTF-8 should be reported, not panic
+#[test]
+fn invalid_utf8_value() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let db = sled::open(temp_dir.path())?;
+    db.insert("key1", &[0xff, 0xfe][..])?;
+    db.flush()?;
+    drop(db);
+
+    let store = SledKvsEngine::open(temp_dir.path())?;
+    assert!(matches!(
+        store.get("key1".to_owned()),
+        Err(KvsError::Utf8(_))
+    ));
+
+    Ok(())
+}
+
+// A database locked by another handle should fail to open with a sled error
+#[test]
+fn locked_database() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let _store = SledKvsEngine::open(temp_dir.path())?;
+
+    assert!(matches!(
+        SledKvsEngine::open(temp_dir.path()),
+        Err(KvsError::Sled(_))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use kvs::{NaiveThreadPool, Result, SharedQueueThreadPool, ThreadPool, WorkStealingThreadPool};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+const JOBS: usize = 100;
+
+// Every job runs, spread over the pool's threads
+fn runs_all_jobs<P: ThreadPool>() -> Result<()> {
+    let pool = P::new(4)?;
+    let counter = Arc::new(AtomicUsize::new(0));
+    let (done, finished) = mpsc::channel();
+    for _ in 0..JOBS {
+        let counter = Arc::clone(&counter);
+        let done = done.clone();
+        pool.spawn(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            done.send(()).unwrap();
+        });
+    }
+    for _ in 0..JOBS {
+        finished.recv_timeout(Duration::from_secs(10)).expect("a job did not run");
+    }
+    assert_eq!(counter.load(Ordering::SeqCst), JOBS);
+    Ok(())
+}
+
+// A panicking job leaves the pool able to run the others, even with a
+// single thread
+fn survives_panics<P: ThreadPool>() -> Result<()> {
+    let pool = P::new(1)?;
+    for _ in 0..3 {
+        pool.spawn(|| panic!("job failed on purpose"));
+    }
+    let (done, finished) = mpsc::channel();
+    pool.spawn(move || done.send(()).unwrap());
+    finished
+        .recv_timeout(Duration::from_secs(10))
+        .expect("the pool stopped after a panicking job");
+    Ok(())
+}
+
+macro_rules! pool_tests {
+    ($name:ident, $pool:ty) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn runs_all_jobs() -> Result<()> {
+                super::runs_all_jobs::<$pool>()
+            }
+
+            #[test]
+            fn survives_panics() -> Result<()> {
+                super::survives_panics::<$pool>()
+            }
+
+            #[test]
+            fn rejects_zero_threads() {
+                assert!(<$pool>::new(0).is_err());
+            }
+        }
+    };
+}
+
+pool_tests!(naive, NaiveThreadPool);
+pool_tests!(shared_queue, SharedQueueThreadPool);
+pool_tests!(work_stealing, WorkStealingThreadPool);
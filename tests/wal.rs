@@ -0,0 +1,133 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use kvs::{KvsError, Result, SyncPolicy, Wal};
+use tempfile::TempDir;
+
+fn payloads(wal: &Wal, from_gen: u64) -> Result<Vec<Vec<u8>>> {
+    wal.replay(from_gen)
+        .map(|record| record.map(|(_, payload)| payload))
+        .collect()
+}
+
+// Records should be readable by position and replayed in order after reopen
+#[test]
+fn append_read_replay() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut wal = Wal::open(temp_dir.path(), SyncPolicy::Always)?;
+
+    let first = wal.append(b"first")?;
+    let second = wal.append(b"second")?;
+    assert_eq!(wal.read(&second)?, b"second");
+    assert_eq!(wal.read(&first)?, b"first");
+
+    drop(wal);
+    let wal = Wal::open(temp_dir.path(), SyncPolicy::Never)?;
+    assert_eq!(payloads(&wal, 0)?, vec![b"first".to_vec(), b"second".to_vec()]);
+    assert_eq!(wal.generations().len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn roll_and_truncate() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut wal = Wal::open(temp_dir.path(), SyncPolicy::Never)?;
+
+    wal.append(b"old")?;
+    let gen = wal.roll()?;
+    wal.append(b"new")?;
+    assert_eq!(payloads(&wal, gen)?, vec![b"new".to_vec()]);
+
+    wal.truncate_before(gen)?;
+    assert_eq!(wal.generations(), &[gen]);
+    assert_eq!(payloads(&wal, 0)?, vec![b"new".to_vec()]);
+
+    Ok(())
+}
+
+// A half-written record at the end of the log is dropped on open
+#[test]
+fn torn_tail_is_truncated() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut wal = Wal::open(temp_dir.path(), SyncPolicy::Never)?;
+    let pos = wal.append(b"intact")?;
+    drop(wal);
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(temp_dir.path().join(format!("{}.log", pos.gen)))?;
+    file.write_all(&[0, 0, 0, 100, 1, 2])?;
+    drop(file);
+
+    let mut wal = Wal::open(temp_dir.path(), SyncPolicy::Never)?;
+    wal.append(b"after")?;
+    assert_eq!(payloads(&wal, 0)?, vec![b"intact".to_vec(), b"after".to_vec()]);
+
+    Ok(())
+}
+
+// Space the filesystem allocated but never wrote reads back as zeros
+#[test]
+fn zero_filled_tail_is_truncated() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut wal = Wal::open(temp_dir.path(), SyncPolicy::Never)?;
+    let pos = wal.append(b"intact")?;
+    drop(wal);
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(temp_dir.path().join(format!("{}.log", pos.gen)))?;
+    file.write_all(&[0; 4096])?;
+    drop(file);
+
+    let wal = Wal::open(temp_dir.path(), SyncPolicy::Never)?;
+    assert_eq!(payloads(&wal, 0)?, vec![b"intact".to_vec()]);
+
+    Ok(())
+}
+
+// Damage in the middle of a segment must not be silently skipped
+#[test]
+fn corruption_is_reported() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut wal = Wal::open(temp_dir.path(), SyncPolicy::Never)?;
+    let pos = wal.append(b"first")?;
+    wal.append(b"second")?;
+    drop(wal);
+
+    let path = temp_dir.path().join(format!("{}.log", pos.gen));
+    let mut data = std::fs::read(&path)?;
+    data[pos.len as usize - 1] ^= 0xff;
+    std::fs::write(&path, data)?;
+
+    assert!(Wal::open(temp_dir.path(), SyncPolicy::Never).is_err());
+
+    Ok(())
+}
+
+// A damaged length in the newest segment can look like a torn record, but
+// the records after it must not be truncated
+#[test]
+fn bad_length_is_not_torn() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut wal = Wal::open(temp_dir.path(), SyncPolicy::Never)?;
+    let pos = wal.append(b"first")?;
+    wal.append(b"second")?;
+    wal.append(b"third")?;
+    drop(wal);
+
+    let path = temp_dir.path().join(format!("{}.log", pos.gen));
+    let mut data = std::fs::read(&path)?;
+    let size = data.len();
+    data[pos.pos as usize] ^= 0x80;
+    std::fs::write(&path, data)?;
+
+    assert!(matches!(
+        Wal::open(temp_dir.path(), SyncPolicy::Never),
+        Err(KvsError::Corruption(_))
+    ));
+    assert_eq!(std::fs::metadata(&path)?.len(), size as u64);
+
+    Ok(())
+}